@@ -1,55 +1,30 @@
 //! Functionality related to running `wasm-bindgen`.
 
 use crate::child;
-use crate::command::build::BuildProfile;
 use crate::install::{self, Tool};
-use crate::manifest::CrateData;
+use crate::manifest::CargoWasmPackProfile;
 use anyhow::{Context, Result};
 use semver;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::process::Command;
 
-/// Run the `wasm-bindgen` CLI to generate bindings for the current crate's
-/// `.wasm`.
+/// Run the `wasm-bindgen` CLI to generate bindings for the `.wasm` at
+/// `wasm_path` in `out_dir`.
 pub fn wasm_bindgen_build(
-    data: &CrateData,
     install_status: &install::Status,
+    wasm_path: &Path,
+    out_dir: &Path,
     out_name: &Option<String>,
     weak_refs: bool,
     reference_types: bool,
-    profile: BuildProfile,
-    extra_options: &Vec<String>,
-) -> Result<PathBuf> {
-    let profile_name = match profile.clone() {
-        BuildProfile::Release | BuildProfile::Profiling => "release",
-        BuildProfile::Dev => "debug",
-        BuildProfile::Custom(profile_name) => &profile_name.clone(),
-    };
-
-    let target_directory = {
-        let mut has_target_dir_iter = extra_options.iter();
-        has_target_dir_iter
-            .find(|&it| it == "--target-dir")
-            .and_then(|_| has_target_dir_iter.next())
-            .map(Path::new)
-            .unwrap_or(data.target_directory())
-    };
-
-    let wasm_path = target_directory
-        .join("wasm32-unknown-unknown")
-        .join(profile_name)
-        .join(data.crate_name())
-        .with_extension("wasm");
-
-    let out_dir_path = target_directory.join("wasm-bindgen");
-    let out_dir = out_dir_path.to_str().unwrap();
-
+    profile: &CargoWasmPackProfile,
+) -> Result<()> {
     let dts_arg = "--typescript";
     let bindgen_path = install::get_tool_path(install_status, Tool::WasmBindgen)?
         .binary(&Tool::WasmBindgen.to_string())?;
 
     let mut cmd = Command::new(&bindgen_path);
-    cmd.arg(&wasm_path)
+    cmd.arg(wasm_path)
         .arg("--out-dir")
         .arg(out_dir)
         .arg(dts_arg);
@@ -73,7 +48,6 @@ pub fn wasm_bindgen_build(
         cmd.arg("--out-name").arg(value);
     }
 
-    let profile = data.configured_profile(profile);
     if profile.wasm_bindgen_debug_js_glue() {
         cmd.arg("--debug");
     }
@@ -91,7 +65,7 @@ pub fn wasm_bindgen_build(
     }
 
     child::run(cmd, "wasm-bindgen").context("Running the wasm-bindgen CLI")?;
-    Ok(out_dir_path)
+    Ok(())
 }
 
 /// Check if the `wasm-bindgen` dependency is locally satisfied for the --target flag
@@ -4,60 +4,129 @@ use crate::child;
 use crate::command::build::BuildProfile;
 use crate::PBAR;
 use anyhow::{anyhow, bail, Context, Result};
-use std::path::Path;
+use cargo_metadata::{Artifact, Message, PackageId};
+use cargo_platform::{Cfg, CfgExpr};
+use semver::Version;
+use std::env;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::str;
 
 pub mod wasm_target;
 
-/// Ensure that `rustc` is present and that it is >= 1.30.0
-pub fn check_rustc_version() -> Result<String> {
-    let local_minor_version = rustc_minor_version();
-    match local_minor_version {
-        Some(mv) => {
-            if mv < 30 {
-                bail!(
-                    "Your version of Rust, '1.{}', is not supported. Please install Rust version 1.30.0 or higher.",
-                    mv.to_string()
-                )
-            } else {
-                Ok(mv.to_string())
-            }
+/// Selects what `cargo build` compiles, and how.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CargoOptions {
+    /// The toolchain to build with, instead of the one that rustup picks.
+    pub toolchain: Option<String>,
+    /// The target to compile for.
+    pub target: String,
+    /// Workspace members to build. When empty, the crate at the build path is
+    /// built.
+    pub packages: Vec<String>,
+    /// Build the library of each package.
+    pub lib: bool,
+    /// Example targets to build, in addition to the library.
+    pub examples: Vec<String>,
+    /// Features to activate.
+    pub features: Vec<String>,
+    /// Don't activate the `default` feature.
+    pub no_default_features: bool,
+    /// Activate all available features.
+    pub all_features: bool,
+    /// Flags for `rustc`, added to the ones from `RUSTFLAGS` or
+    /// `CARGO_ENCODED_RUSTFLAGS` when either is set. Otherwise they are added
+    /// to the `target` rustflags of cargo's config, or to its
+    /// `build.rustflags` when it has no `target` rustflags.
+    pub rustflags: Vec<String>,
+    /// Build the library with `cargo rustc --crate-type cdylib`, so that it
+    /// doesn't have to declare a cdylib target. `cargo rustc` only builds a
+    /// single package.
+    pub as_cdylib: bool,
+}
+
+impl Default for CargoOptions {
+    fn default() -> Self {
+        CargoOptions {
+            toolchain: None,
+            target: wasm_target::DEFAULT_TARGET.to_string(),
+            packages: Vec::new(),
+            lib: true,
+            examples: Vec::new(),
+            features: Vec::new(),
+            no_default_features: false,
+            all_features: false,
+            rustflags: Vec::new(),
+            as_cdylib: false,
         }
-        None => bail!("We can't figure out what your Rust version is- which means you might not have Rust installed. Please install Rust version 1.30.0 or higher."),
     }
 }
 
-// from https://github.com/alexcrichton/proc-macro2/blob/79e40a113b51836f33214c6d00228934b41bd4ad/build.rs#L44-L61
-fn rustc_minor_version() -> Option<u32> {
-    macro_rules! otry {
-        ($e:expr) => {
-            match $e {
-                Some(e) => e,
-                None => return None,
-            }
-        };
+/// Create a `cargo`, `rustc` or `rustup` command that runs in `dir`, so that
+/// rustup picks the toolchain from the `rust-toolchain.toml` that applies
+/// there, unless `toolchain` names another one, like `+toolchain` would.
+pub fn rust_command(program: &str, dir: &Path, toolchain: Option<&str>) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.current_dir(dir);
+    if let Some(toolchain) = toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
     }
-    let output = otry!(Command::new("rustc").arg("--version").output().ok());
-    let version = otry!(str::from_utf8(&output.stdout).ok());
-    let mut pieces = version.split('.');
-    if pieces.next() != Some("rustc 1") {
-        return None;
+    cmd
+}
+
+/// Get the version of the `rustc` that builds in `dir` would use.
+pub fn check_rustc_version(dir: &Path, toolchain: Option<&str>) -> Result<Version> {
+    let output = rust_command("rustc", dir, toolchain)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success());
+    // The output looks like `rustc 1.80.0-nightly (hash date)`
+    let version = output.and_then(|output| {
+        let version = String::from_utf8(output.stdout).ok()?;
+        Version::parse(version.split_whitespace().nth(1)?).ok()
+    });
+    match version {
+        Some(version) => Ok(version),
+        None => match toolchain {
+            Some(toolchain) => bail!(
+                "We can't figure out what the Rust version of the `{}` toolchain is. Is it installed?",
+                toolchain
+            ),
+            None => bail!("We can't figure out what your Rust version is- which means you might not have Rust installed."),
+        },
     }
-    otry!(pieces.next()).parse().ok()
 }
 
-/// Run `cargo build` targetting `wasm32-unknown-unknown`.
+/// Run `cargo build` targetting the target in `options`, or `cargo rustc` when
+/// the library is built as a cdylib regardless of its manifest. `cargo rustc`
+/// can only pass `--crate-type` to a single target, so it doesn't build
+/// examples.
+///
+/// Returns the artifacts that cargo reported, so that their paths don't have
+/// to be guessed from the profile and target directory.
 pub fn cargo_build_wasm(
     path: &Path,
     profile: BuildProfile,
+    options: &CargoOptions,
     extra_options: &[String],
-) -> Result<()> {
+) -> Result<Vec<Artifact>> {
     let msg = format!("Compiling to Wasm...");
     PBAR.info(&msg);
 
-    let mut cmd = Command::new("cargo");
-    cmd.current_dir(path).arg("build").arg("--lib");
+    let subcommand = if options.as_cdylib { "rustc" } else { "build" };
+    let mut cmd = rust_command("cargo", path, options.toolchain.as_deref());
+    cmd.arg(subcommand)
+        .arg("--message-format=json-render-diagnostics");
+    if options.lib {
+        cmd.arg("--lib");
+    }
+    for example in &options.examples {
+        cmd.arg("--example").arg(example);
+    }
+    if options.as_cdylib {
+        cmd.arg("--crate-type").arg("cdylib");
+    }
 
     if PBAR.quiet() {
         cmd.arg("--quiet");
@@ -65,11 +134,10 @@ pub fn cargo_build_wasm(
 
     match profile {
         BuildProfile::Profiling => {
-            // Once there are DWARF debug info consumers, force enable debug
-            // info, because builds that use the release cargo profile disables
-            // debug info.
-            //
-            // cmd.env("RUSTFLAGS", "-g");
+            // The release cargo profile disables debug info, and strips what
+            // the standard library brings along, so turn it back on to keep
+            // function names in the name section.
+            cmd.env("CARGO_PROFILE_RELEASE_DEBUG", "true");
             cmd.arg("--release");
         }
         BuildProfile::Release => {
@@ -84,7 +152,39 @@ pub fn cargo_build_wasm(
         }
     }
 
-    cmd.arg("--target").arg("wasm32-unknown-unknown");
+    cmd.arg("--target").arg(&options.target);
+
+    for package in &options.packages {
+        cmd.arg("--package").arg(package);
+    }
+
+    if !options.features.is_empty() {
+        cmd.arg("--features").arg(options.features.join(","));
+    }
+    if options.no_default_features {
+        cmd.arg("--no-default-features");
+    }
+    if options.all_features {
+        cmd.arg("--all-features");
+    }
+
+    if !options.rustflags.is_empty() {
+        // Flags from the environment replace the ones from cargo's config, so
+        // only add to the environment when the user has already set it
+        if env::var_os("CARGO_ENCODED_RUSTFLAGS").is_some() || env::var_os("RUSTFLAGS").is_some() {
+            cmd.env(
+                "CARGO_ENCODED_RUSTFLAGS",
+                encoded_rustflags(&options.rustflags),
+            );
+        } else {
+            // cargo ignores `build.rustflags` once there are `target` flags,
+            // which the ones from `--config` are, so pass them along
+            let mut rustflags = config_build_rustflags(path, options, extra_options)?;
+            rustflags.extend(options.rustflags.iter().cloned());
+            cmd.arg("--config")
+                .arg(rustflags_config(&options.target, &rustflags));
+        }
+    }
 
     // The `cargo` command is executed inside the directory at `path`, so relative paths set via extra options won't work.
     // To remedy the situation, all detected paths are converted to absolute paths.
@@ -107,11 +207,204 @@ pub fn cargo_build_wasm(
         .collect::<Result<Vec<_>>>()?;
     cmd.args(extra_options_with_absolute_paths);
 
-    child::run(cmd, "cargo build").context("Compiling your crate to WebAssembly failed")?;
-    Ok(())
+    child::run_read_stdout(cmd, &format!("cargo {}", subcommand), |stdout| {
+        let mut artifacts = Vec::new();
+        for message in Message::parse_stream(BufReader::new(stdout)) {
+            if let Message::CompilerArtifact(artifact) = message? {
+                artifacts.push(artifact);
+            }
+        }
+        Ok(artifacts)
+    })
+    .context("Compiling your crate to WebAssembly failed")
 }
 
-/// Runs `cargo build --tests` targeting `wasm32-unknown-unknown`.
+/// A `--config` value that adds `rustflags` to `target.<target>.rustflags`.
+/// cargo appends arrays from `--config` to the ones from its config files, so
+/// the flags from `target.<target>.rustflags` and `target.<cfg>.rustflags` are
+/// kept.
+fn rustflags_config(target: &str, rustflags: &[String]) -> String {
+    let flags = rustflags
+        .iter()
+        .map(|flag| toml::Value::String(flag.clone()))
+        .collect();
+    format!(
+        "target.{}.rustflags={}",
+        toml::Value::String(target.to_string()),
+        toml::Value::Array(flags)
+    )
+}
+
+/// The `build.rustflags` of cargo's config for a build in `dir` with
+/// `extra_options`, if cargo would use them: it doesn't when
+/// `target.<triple>.rustflags` or a matching `target.<cfg>.rustflags` is set.
+///
+/// cargo resolves its config itself with `cargo config get`, which is
+/// unstable, so it is run with `RUSTC_BOOTSTRAP=1`.
+pub fn config_build_rustflags(
+    dir: &Path,
+    options: &CargoOptions,
+    extra_options: &[String],
+) -> Result<Vec<String>> {
+    let toolchain = options.toolchain.as_deref();
+    let config_args = config_args(extra_options);
+    let get = |key: &str| cargo_config_get(dir, toolchain, &config_args, key);
+
+    if get(&format!("target.{}.rustflags", options.target))?.is_some() {
+        return Ok(Vec::new());
+    }
+    if let Some(serde_json::Value::Object(targets)) = get("target")? {
+        let cfg_keys: Vec<&String> = targets
+            .iter()
+            .filter(|(key, value)| key.starts_with("cfg(") && value.get("rustflags").is_some())
+            .map(|(key, _)| key)
+            .collect();
+        if !cfg_keys.is_empty() {
+            let cfgs = target_cfgs(dir, toolchain, &options.target)?;
+            if cfg_keys.iter().any(|key| CfgExpr::matches_key(key, &cfgs)) {
+                return Ok(Vec::new());
+            }
+        }
+    }
+
+    match get("build.rustflags")? {
+        // Flags from the environment are a single string
+        Some(serde_json::Value::String(flags)) => {
+            Ok(flags.split_whitespace().map(String::from).collect())
+        }
+        Some(serde_json::Value::Array(flags)) => flags
+            .iter()
+            .map(|flag| {
+                flag.as_str()
+                    .map(String::from)
+                    .ok_or_else(|| anyhow!("`build.rustflags` must be a list of strings"))
+            })
+            .collect(),
+        Some(_) => bail!("`build.rustflags` must be a string or a list of strings"),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The `--config` and `-Z` options in `extra_options`, which change cargo's
+/// config for the build.
+fn config_args(extra_options: &[String]) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut options = extra_options.iter();
+    while let Some(option) = options.next() {
+        if option == "--config" || option == "-Z" {
+            args.push(option.clone());
+            args.extend(options.next().cloned());
+        } else if option.starts_with("--config=") || option.starts_with("-Z") {
+            args.push(option.clone());
+        }
+    }
+    args
+}
+
+/// Get the value of `key` in cargo's config as JSON, or `None` if it isn't
+/// set.
+fn cargo_config_get(
+    dir: &Path,
+    toolchain: Option<&str>,
+    config_args: &[String],
+    key: &str,
+) -> Result<Option<serde_json::Value>> {
+    let output = rust_command("cargo", dir, toolchain)
+        .env("RUSTC_BOOTSTRAP", "1")
+        .args(config_args)
+        .args([
+            "-Zunstable-options",
+            "config",
+            "get",
+            "--format",
+            "json-value",
+        ])
+        .arg(key)
+        .output()
+        .context("failed to run `cargo config get`")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("is not set") {
+            return Ok(None);
+        }
+        bail!("`cargo config get {}` failed: {}", key, stderr.trim());
+    }
+    let value = serde_json::from_slice(&output.stdout)
+        .with_context(|| anyhow!("failed to parse the output of `cargo config get {}`", key))?;
+    Ok(Some(value))
+}
+
+/// The cfg values of `target`, which `target.<cfg>` tables are matched
+/// against.
+fn target_cfgs(dir: &Path, toolchain: Option<&str>, target: &str) -> Result<Vec<Cfg>> {
+    let output = rust_command("rustc", dir, toolchain)
+        .args(["--print", "cfg", "--target", target])
+        .output()
+        .context("failed to run `rustc --print cfg`")?;
+    if !output.status.success() {
+        bail!(
+            "`rustc --print cfg --target {}` failed: {}",
+            target,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.parse().ok())
+        .collect())
+}
+
+/// Append `rustflags` to the flags that cargo would take from the environment,
+/// in the `CARGO_ENCODED_RUSTFLAGS` format, which takes precedence over
+/// `RUSTFLAGS`.
+fn encoded_rustflags(rustflags: &[String]) -> String {
+    let mut flags: Vec<String> = match env::var("CARGO_ENCODED_RUSTFLAGS") {
+        Ok(encoded) => encoded
+            .split('\x1f')
+            .filter(|flag| !flag.is_empty())
+            .map(String::from)
+            .collect(),
+        Err(_) => env::var("RUSTFLAGS")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(String::from)
+            .collect(),
+    };
+    flags.extend(rustflags.iter().cloned());
+    flags.join("\x1f")
+}
+
+/// Find the `.wasm` file that cargo built for the cdylib target of the
+/// package `package_id`, or for its example target `example`.
+pub fn find_wasm_artifact(
+    artifacts: &[Artifact],
+    package_id: &PackageId,
+    example: Option<&str>,
+) -> Result<PathBuf> {
+    artifacts
+        .iter()
+        .filter(|artifact| &artifact.package_id == package_id)
+        .filter(|artifact| match example {
+            Some(example) => {
+                artifact.target.name == example
+                    && artifact.target.kind.iter().any(|k| k == "example")
+            }
+            None => artifact.target.kind.iter().any(|k| k == "cdylib"),
+        })
+        .flat_map(|artifact| artifact.filenames.iter())
+        .find(|filename| filename.extension() == Some("wasm"))
+        .map(|filename| filename.clone().into_std_path_buf())
+        .ok_or_else(|| match example {
+            Some(example) => anyhow!(
+                "cargo did not report a wasm artifact for the example `{}` of {}",
+                example,
+                package_id
+            ),
+            None => anyhow!("cargo did not report a wasm artifact for {}", package_id),
+        })
+}
+
+/// Runs `cargo build --tests` targeting `target`.
 ///
 /// This generates the `Cargo.lock` file that we use in order to know which version of
 /// wasm-bindgen-cli to use when running tests.
@@ -123,11 +416,19 @@ pub fn cargo_build_wasm(
 ///
 /// * `path`: Path to the crate directory to build tests.
 /// * `debug`: Whether to build tests in `debug` mode.
+/// * `target`: The target to build tests for.
+/// * `toolchain`: The toolchain to build tests with, instead of the one that rustup picks.
 /// * `extra_options`: Additional parameters to pass to `cargo` when building tests.
-pub fn cargo_build_wasm_tests(path: &Path, debug: bool, extra_options: &[String]) -> Result<()> {
-    let mut cmd = Command::new("cargo");
+pub fn cargo_build_wasm_tests(
+    path: &Path,
+    debug: bool,
+    target: &str,
+    toolchain: Option<&str>,
+    extra_options: &[String],
+) -> Result<()> {
+    let mut cmd = rust_command("cargo", path, toolchain);
 
-    cmd.current_dir(path).arg("build").arg("--tests");
+    cmd.arg("build").arg("--tests");
 
     if PBAR.quiet() {
         cmd.arg("--quiet");
@@ -137,7 +438,7 @@ pub fn cargo_build_wasm_tests(path: &Path, debug: bool, extra_options: &[String]
         cmd.arg("--release");
     }
 
-    cmd.arg("--target").arg("wasm32-unknown-unknown");
+    cmd.arg("--target").arg(target);
 
     cmd.args(extra_options);
 
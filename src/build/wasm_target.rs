@@ -1,15 +1,19 @@
 //! Checking for the wasm32 target
 
+use crate::build::rust_command;
 use crate::child;
 use crate::PBAR;
 use anyhow::{anyhow, bail, Context, Result};
 use log::error;
 use log::info;
 use std::fmt;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+
+/// The target that crates are compiled for, unless another one is configured.
+pub const DEFAULT_TARGET: &str = "wasm32-unknown-unknown";
 
 struct Wasm32Check {
+    target: String,
     rustc_path: PathBuf,
     sysroot: PathBuf,
     found: bool,
@@ -18,7 +22,7 @@ struct Wasm32Check {
 
 impl fmt::Display for Wasm32Check {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let target = "wasm32-unknown-unknown";
+        let target = &self.target;
 
         if !self.found {
             let rustup_string = if self.is_rustup {
@@ -50,14 +54,14 @@ impl fmt::Display for Wasm32Check {
     }
 }
 
-/// Ensure that `rustup` has the `wasm32-unknown-unknown` target installed for
-/// current toolchain
-pub fn check_for_wasm32_target() -> Result<()> {
+/// Ensure that `rustup` has the `target` installed for the toolchain that
+/// builds in `dir` use, or `toolchain` if given
+pub fn check_for_wasm32_target(target: &str, dir: &Path, toolchain: Option<&str>) -> Result<()> {
     let msg = format!("Checking for the Wasm target...");
     PBAR.info(&msg);
 
     // Check if wasm32 target is present, otherwise bail.
-    match check_wasm32_target() {
+    match check_wasm32_target(target, dir, toolchain) {
         Ok(ref wasm32_check) if wasm32_check.found => Ok(()),
         Ok(wasm32_check) => bail!("{}", wasm32_check),
         Err(err) => Err(err),
@@ -65,8 +69,8 @@ pub fn check_for_wasm32_target() -> Result<()> {
 }
 
 /// Get rustc's sysroot as a PathBuf
-fn get_rustc_sysroot() -> Result<PathBuf> {
-    let command = Command::new("rustc")
+fn get_rustc_sysroot(dir: &Path, toolchain: Option<&str>) -> Result<PathBuf> {
+    let command = rust_command("rustc", dir, toolchain)
         .args(&["--print", "sysroot"])
         .output()?;
 
@@ -80,42 +84,35 @@ fn get_rustc_sysroot() -> Result<PathBuf> {
     }
 }
 
-/// Get wasm32-unknown-unknown target libdir
-fn get_rustc_wasm32_unknown_unknown_target_libdir() -> Result<PathBuf> {
-    let command = Command::new("rustc")
-        .args(&[
-            "--target",
-            "wasm32-unknown-unknown",
-            "--print",
-            "target-libdir",
-        ])
+/// Get the libdir of `target`
+fn get_rustc_target_libdir(target: &str, dir: &Path, toolchain: Option<&str>) -> Result<PathBuf> {
+    let command = rust_command("rustc", dir, toolchain)
+        .args(&["--target", target, "--print", "target-libdir"])
         .output()?;
 
     if command.status.success() {
         Ok(String::from_utf8(command.stdout)?.trim().into())
     } else {
         Err(anyhow!(
-            "Getting rustc's wasm32-unknown-unknown target wasn't successful. Got {}",
+            "Getting rustc's {} target wasn't successful. Got {}",
+            target,
             command.status
         ))
     }
 }
 
-fn does_wasm32_target_libdir_exist() -> bool {
-    let result = get_rustc_wasm32_unknown_unknown_target_libdir();
+fn does_wasm32_target_libdir_exist(target: &str, dir: &Path, toolchain: Option<&str>) -> bool {
+    let result = get_rustc_target_libdir(target, dir, toolchain);
 
     match result {
         Ok(wasm32_target_libdir_path) => {
             if wasm32_target_libdir_path.exists() {
-                info!(
-                    "Found wasm32-unknown-unknown in {:?}",
-                    wasm32_target_libdir_path
-                );
+                info!("Found {} in {:?}", target, wasm32_target_libdir_path);
                 true
             } else {
                 info!(
-                    "Failed to find wasm32-unknown-unknown in {:?}",
-                    wasm32_target_libdir_path
+                    "Failed to find {} in {:?}",
+                    target, wasm32_target_libdir_path
                 );
                 false
             }
@@ -127,12 +124,14 @@ fn does_wasm32_target_libdir_exist() -> bool {
     }
 }
 
-fn check_wasm32_target() -> Result<Wasm32Check> {
-    let sysroot = get_rustc_sysroot()?;
+fn check_wasm32_target(target: &str, dir: &Path, toolchain: Option<&str>) -> Result<Wasm32Check> {
+    let sysroot = get_rustc_sysroot(dir, toolchain)?;
     let rustc_path = which::which("rustc")?;
+    let target = target.to_string();
 
-    if does_wasm32_target_libdir_exist() {
+    if does_wasm32_target_libdir_exist(&target, dir, toolchain) {
         Ok(Wasm32Check {
+            target,
             rustc_path,
             sysroot,
             found: true,
@@ -141,9 +140,10 @@ fn check_wasm32_target() -> Result<Wasm32Check> {
     // If it doesn't exist, then we need to check if we're using rustup.
     } else {
         // If sysroot contains "rustup", then we can assume we're using rustup
-        // and use rustup to add the wasm32-unknown-unknown target.
+        // and use rustup to add the target.
         if sysroot.to_string_lossy().contains("rustup") {
-            rustup_add_wasm_target().map(|()| Wasm32Check {
+            rustup_add_wasm_target(&target, dir, toolchain).map(|()| Wasm32Check {
+                target,
                 rustc_path,
                 sysroot,
                 found: true,
@@ -151,6 +151,7 @@ fn check_wasm32_target() -> Result<Wasm32Check> {
             })
         } else {
             Ok(Wasm32Check {
+                target,
                 rustc_path,
                 sysroot,
                 found: false,
@@ -160,11 +161,12 @@ fn check_wasm32_target() -> Result<Wasm32Check> {
     }
 }
 
-/// Add wasm32-unknown-unknown using `rustup`.
-fn rustup_add_wasm_target() -> Result<()> {
-    let mut cmd = Command::new("rustup");
-    cmd.arg("target").arg("add").arg("wasm32-unknown-unknown");
-    child::run(cmd, "rustup").context("Adding the wasm32-unknown-unknown target with rustup")?;
+/// Add `target` using `rustup`.
+fn rustup_add_wasm_target(target: &str, dir: &Path, toolchain: Option<&str>) -> Result<()> {
+    let mut cmd = rust_command("rustup", dir, toolchain);
+    cmd.arg("target").arg("add").arg(target);
+    child::run(cmd, "rustup")
+        .with_context(|| format!("Adding the {} target with rustup", target))?;
 
     Ok(())
 }
@@ -6,7 +6,7 @@
 use crate::install::Tool;
 use anyhow::{bail, Result};
 use log::info;
-use std::process::{Command, Stdio};
+use std::process::{ChildStdout, Command, Stdio};
 
 /// Return a new Command object
 pub fn new_command(program: &str) -> Command {
@@ -42,6 +42,31 @@ pub fn run(mut command: Command, command_name: &str) -> Result<()> {
     }
 }
 
+/// Run the given command, handing its stdout to `read_stdout` while it runs.
+/// Returns the result of `read_stdout` on success.
+pub fn run_read_stdout<T>(
+    mut command: Command,
+    command_name: &str,
+    read_stdout: impl FnOnce(ChildStdout) -> Result<T>,
+) -> Result<T> {
+    info!("Running {:?}", command);
+
+    let mut child = command.stdout(Stdio::piped()).spawn()?;
+    let result = read_stdout(child.stdout.take().unwrap());
+    let status = child.wait()?;
+
+    if status.success() {
+        result
+    } else {
+        bail!(
+            "failed to execute `{}`: exited with {}\n  full command: {:?}",
+            command_name,
+            status,
+            command,
+        )
+    }
+}
+
 /// Run the given command and return its stdout.
 pub fn run_capture_stdout(mut command: Command, command_name: &Tool) -> Result<String> {
     info!("Running {:?}", command);
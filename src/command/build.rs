@@ -1,18 +1,30 @@
 use crate::bindgen;
 use crate::build;
+use crate::build::wasm_target::DEFAULT_TARGET;
+use crate::custom_sections;
 use crate::install::{self, InstallMode, Tool};
-use crate::js_bin::WasmJsWriter;
+use crate::js_bin::{
+    self, LoaderOptions, LoaderTarget, RuntimeOptions, WasmJsWriter, RUNTIME_FILENAME,
+};
+use crate::js_project;
 use crate::lockfile::Lockfile;
-use crate::manifest;
+use crate::manifest::{self, SettingSource};
+use crate::post_process;
+use crate::pre_initialize;
 use crate::utils::*;
+use crate::wasm2js;
 use crate::wasm_opt;
+use crate::wasm_snip;
 use crate::PBAR;
 
 use anyhow::{anyhow, bail, Result};
 use binary_install::Cache;
+use cargo_metadata::Artifact;
 use clap::Args;
 use log::info;
 use path_clean::PathClean;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::fs::File;
 use std::io::BufWriter;
@@ -24,19 +36,82 @@ use std::time::Instant;
 /// Everything required to configure and run the `wasm-js build` command.
 pub struct Build {
     pub crate_path: PathBuf,
-    pub crate_data: manifest::CrateData,
-    pub weak_refs: bool,
-    pub reference_types: bool,
+    pub crates: Vec<manifest::CrateData>,
+    pub cargo_options: build::CargoOptions,
+    /// `--weak-refs` or `--no-weak-refs`, which take priority over the
+    /// package metadata.
+    pub weak_refs: Option<bool>,
+    /// `--reference-types` or `--no-reference-types`, which take priority
+    /// over the package metadata.
+    pub reference_types: Option<bool>,
     pub no_opt: bool,
     pub profile: BuildProfile,
     pub mode: InstallMode,
-    pub out_dir: PathBuf,
+    pub target: String,
+    pub as_cdylib: bool,
+    /// Example targets that are built next to the libraries.
+    pub examples: Vec<String>,
+    /// The features from the command line, which `output` entries build with
+    /// in addition to their own.
+    pub cli_features: Vec<String>,
+    /// The output directory of each crate by package name, and where it came
+    /// from.
+    pub out_dirs: BTreeMap<String, (PathBuf, SettingSource)>,
     pub out_name: Option<String>,
-    pub bindgen: Option<install::Status>,
     pub cache: Cache,
     pub extra_options: Vec<String>,
 }
 
+/// A `.wasm` file that cargo built for a library or an example, which is
+/// turned into its own Javascript module.
+struct WasmModule<'a> {
+    crate_data: &'a manifest::CrateData,
+    /// The example target, or `None` for the library.
+    example: Option<&'a str>,
+    /// The `output` entry of the library that this module is a flavor of.
+    output: Option<&'a manifest::CargoWasmPackOutput>,
+    /// The settings for this module, with the command line overrides applied.
+    profile: manifest::CargoWasmPackProfile,
+    wasm_path: PathBuf,
+    /// The variant built with the `enhanced-target-features` of the profile.
+    enhanced: Option<WasmVariant>,
+}
+
+/// A variant of a module, built with other target features.
+struct WasmVariant {
+    profile: manifest::CargoWasmPackProfile,
+    wasm_path: PathBuf,
+}
+
+impl WasmModule<'_> {
+    /// The prefix of the output file names, which is the name of the example
+    /// for examples, and the out-name of the output entry for outputs.
+    fn name_prefix(&self) -> String {
+        match (self.example, self.output) {
+            (Some(example), _) => example.replace('-', "_"),
+            (None, Some(output)) => output.out_name().to_string(),
+            (None, None) => self.crate_data.name_prefix(),
+        }
+    }
+
+    /// The `--out-name` to pass to `wasm-bindgen`. It is always given, since
+    /// the wasm that wasm-bindgen reads may be a renamed copy of the artifact.
+    fn out_name(&self) -> Option<String> {
+        Some(self.name_prefix())
+    }
+}
+
+impl fmt::Display for WasmModule<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let package = self.crate_data.package_name();
+        match (self.example, self.output) {
+            (Some(example), _) => write!(f, "the example `{}` of {}", example, package),
+            (None, Some(output)) => write!(f, "the `{}` output of {}", output.out_name(), package),
+            (None, None) => write!(f, "{}", package),
+        }
+    }
+}
+
 /// The build profile controls whether optimizations, debug info, and assertions
 /// are enabled or disabled.
 #[derive(Clone, Debug)]
@@ -51,6 +126,17 @@ pub enum BuildProfile {
     Custom(String),
 }
 
+impl fmt::Display for BuildProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildProfile::Dev => write!(f, "dev"),
+            BuildProfile::Release => write!(f, "release"),
+            BuildProfile::Profiling => write!(f, "profiling"),
+            BuildProfile::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
+
 /// Everything required to configure and run the build command.
 #[derive(Debug, Args)]
 #[command(allow_hyphen_values = true, trailing_var_arg = true)]
@@ -59,18 +145,49 @@ pub struct BuildOptions {
     #[clap()]
     pub path: Option<PathBuf>,
 
-    #[clap(long = "mode", short = 'm', default_value = "normal")]
-    /// Sets steps to be run. [possible values: no-install, normal, force]
-    pub mode: InstallMode,
+    #[clap(long = "mode", short = 'm')]
+    /// Sets steps to be run. Defaults to `mode` in the package metadata, or
+    /// normal. [possible values: no-install, normal, force]
+    pub mode: Option<InstallMode>,
+
+    #[clap(long = "toolchain")]
+    /// The Rust toolchain to build with, instead of the one that rustup
+    /// picks, such as the one from `rust-toolchain.toml`.
+    pub toolchain: Option<String>,
+
+    #[clap(long = "target")]
+    /// The Rust target to compile for. Defaults to `target` in the package
+    /// metadata, or wasm32-unknown-unknown.
+    pub target: Option<String>,
+
+    #[clap(long = "cdylib")]
+    /// Build the library as a cdylib even if `Cargo.toml` doesn't declare
+    /// one, with `cargo rustc --crate-type cdylib`.
+    pub cdylib: bool,
+
+    #[clap(long = "example")]
+    /// Also build the given cdylib example target into its own Javascript
+    /// module, named after the example. May be repeated.
+    pub examples: Vec<String>,
 
     #[clap(long = "weak-refs")]
     /// Enable usage of the JS weak references proposal.
     pub weak_refs: bool,
 
+    #[clap(long = "no-weak-refs", conflicts_with = "weak_refs")]
+    /// Disable usage of the JS weak references proposal, even if the package
+    /// metadata enables it.
+    pub no_weak_refs: bool,
+
     #[clap(long = "reference-types")]
     /// Enable usage of WebAssembly reference types.
     pub reference_types: bool,
 
+    #[clap(long = "no-reference-types", conflicts_with = "reference_types")]
+    /// Disable usage of WebAssembly reference types, even if the package
+    /// metadata enables them.
+    pub no_reference_types: bool,
+
     #[clap(long = "debug")]
     /// Deprecated. Renamed to `--dev`.
     pub debug: bool,
@@ -92,12 +209,41 @@ pub struct BuildOptions {
     /// User-defined profile with --profile flag
     pub profile: Option<String>,
 
-    #[clap(long = "out-dir", short = 'd', default_value = "dist")]
-    /// Sets the output directory with a relative path.
-    pub out_dir: String,
+    #[clap(long = "package", short = 'p')]
+    /// Build the given workspace member. May be repeated.
+    pub packages: Vec<String>,
+
+    #[clap(long = "workspace")]
+    /// Build every workspace member that has a cdylib target or
+    /// `package.metadata.wasm-js`.
+    pub workspace: bool,
+
+    #[clap(long = "features", short = 'F')]
+    /// Space or comma separated list of features to activate. Added to the
+    /// `features` of the profile.
+    pub features: Vec<String>,
+
+    #[clap(long = "no-default-features")]
+    /// Do not activate the `default` feature.
+    pub no_default_features: bool,
+
+    #[clap(long = "all-features")]
+    /// Activate all available features.
+    pub all_features: bool,
+
+    #[clap(long = "out-dir", short = 'd')]
+    /// Sets the output directory with a relative path. Defaults to `out-dir`
+    /// in the package metadata, or `dist`.
+    pub out_dir: Option<String>,
+
+    #[clap(long = "tsconfig-out-dir")]
+    /// Without `--out-dir` or `out-dir` in the package metadata, use the
+    /// `compilerOptions.outDir` of the `tsconfig.json` next to `Cargo.toml`.
+    pub tsconfig_out_dir: bool,
 
     #[clap(long = "out-name")]
-    /// Sets the output file names. Defaults to package name.
+    /// Sets the output file names. Defaults to `out-name` in the package
+    /// metadata, or the package name.
     pub out_name: Option<String>,
 
     #[clap(long = "no-opt", alias = "no-optimization")]
@@ -112,16 +258,28 @@ impl Default for BuildOptions {
     fn default() -> Self {
         Self {
             path: None,
-            mode: InstallMode::default(),
+            mode: None,
+            toolchain: None,
+            target: None,
+            cdylib: false,
+            examples: Vec::new(),
             weak_refs: false,
+            no_weak_refs: false,
             reference_types: false,
+            no_reference_types: false,
             debug: false,
             dev: false,
             no_opt: false,
             release: false,
             profiling: false,
             profile: None,
-            out_dir: String::new(),
+            packages: Vec::new(),
+            workspace: false,
+            features: Vec::new(),
+            no_default_features: false,
+            all_features: false,
+            out_dir: None,
+            tsconfig_out_dir: false,
             out_name: None,
             extra_options: Vec::new(),
         }
@@ -140,8 +298,102 @@ impl Build {
             }
         }
         let crate_path = get_crate_path(path_arg)?;
-        let crate_data = manifest::CrateData::new(&crate_path, build_opts.out_name.clone())?;
-        let out_dir = crate_path.join(PathBuf::from(&build_opts.out_dir)).clean();
+        let workspace_mode = build_opts.workspace || !build_opts.packages.is_empty();
+        let crates = if workspace_mode {
+            manifest::CrateData::workspace_members(
+                &crate_path,
+                &build_opts.packages,
+                build_opts.workspace,
+                build_opts.toolchain.as_deref(),
+            )?
+        } else {
+            vec![manifest::CrateData::new(
+                &crate_path,
+                build_opts.out_name.clone(),
+                build_opts.toolchain.as_deref(),
+            )?]
+        };
+        if crates.len() > 1 && build_opts.out_name.is_some() {
+            bail!("--out-name can't be used when building more than one package");
+        }
+        for example in &build_opts.examples {
+            let owners: Vec<_> = crates
+                .iter()
+                .filter(|crate_data| crate_data.has_example(example))
+                .collect();
+            match owners[..] {
+                [owner] => owner.check_example(example)?,
+                [] if crates.len() == 1 => crates[0].check_example(example)?,
+                [] => bail!(
+                    "None of the packages that are built has an example named `{}`.",
+                    example
+                ),
+                [first, second, ..] => bail!(
+                    "Both {} and {} have an example named `{}`. Pick one of them with `--package`.",
+                    first.package_name(),
+                    second.package_name(),
+                    example
+                ),
+            }
+        }
+        // Name the packages to cargo only when they were selected from the
+        // workspace, so that a plain build keeps building the crate at `path`.
+        let packages = if workspace_mode {
+            crates
+                .iter()
+                .map(|crate_data| crate_data.package_name().to_string())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut out_dirs = BTreeMap::new();
+        for crate_data in &crates {
+            let (out_dir, source) = match &build_opts.out_dir {
+                Some(out_dir) => (
+                    crate_path.join(out_dir),
+                    SettingSource::CommandLine("--out-dir".to_string()),
+                ),
+                None => match crate_data.out_dir() {
+                    Some(out_dir) => out_dir,
+                    None => match js_project::infer_out_dir(
+                        crate_data.manifest_dir(),
+                        build_opts.tsconfig_out_dir,
+                    )? {
+                        Some(inferred) => (
+                            inferred.path.clone(),
+                            SettingSource::JsProject(inferred.to_string()),
+                        ),
+                        None => (crate_path.join("dist"), SettingSource::Default),
+                    },
+                },
+            };
+            out_dirs.insert(
+                crate_data.package_name().to_string(),
+                (out_dir.clean(), source),
+            );
+        }
+
+        let mode = match build_opts.mode {
+            Some(mode) => mode,
+            None => agreed_setting(&crates, "mode", |crate_data| {
+                crate_data.mode().map(|(mode, _)| mode).unwrap_or_default()
+            })?,
+        };
+        let target = match &build_opts.target {
+            Some(target) => target.clone(),
+            None => agreed_setting(&crates, "target", |crate_data| {
+                crate_data
+                    .target()
+                    .map_or(DEFAULT_TARGET.to_string(), |(target, _)| target)
+            })?,
+        };
+        if !target.starts_with("wasm32") && !target.starts_with("wasm64") {
+            bail!(
+                "`{}` is not a WebAssembly target. Try `{}` or `wasm32v1-none`.",
+                target,
+                DEFAULT_TARGET
+            );
+        }
 
         let dev = build_opts.dev || build_opts.debug;
         let profile = match (
@@ -159,20 +411,265 @@ impl Build {
             _ => bail!("Can only supply one of the --dev, --release, --profiling, or --profile 'name' flags"),
         };
 
-        Ok(Build {
+        let cli_features: Vec<String> = build_opts
+            .features
+            .iter()
+            .flat_map(|features| features.split([',', ' ']))
+            .filter(|feature| !feature.is_empty())
+            .map(String::from)
+            .collect();
+        let mut features: Vec<String> = Vec::new();
+        let rustflags = match crates.first() {
+            Some(crate_data) => crate_data
+                .configured_profile(profile.clone())?
+                .rustc_flags(),
+            None => Vec::new(),
+        };
+        for crate_data in &crates {
+            let configured = crate_data.configured_profile(profile.clone())?;
+            let name = crate_data.package_name();
+
+            for feature in crate_features(
+                &cli_features,
+                &crates,
+                crate_data,
+                &configured,
+                workspace_mode,
+            )? {
+                if !features.contains(&feature) {
+                    features.push(feature);
+                }
+            }
+
+            // All the crates are compiled by the same `cargo build`
+            if configured.rustc_flags() != rustflags {
+                bail!(
+                    "`rustflags` and `target-features` must be the same for all packages that are built together, but {} and {} differ.",
+                    crates[0].package_name(),
+                    name
+                );
+            }
+            check_profile(crate_data, &configured)?;
+
+            if !crate_data.outputs().is_empty() && build_opts.out_name.is_some() {
+                bail!(
+                    "--out-name can't be used for {}, because it has `output` entries with their own out-names",
+                    name
+                );
+            }
+            for output in crate_data.outputs() {
+                let configured = crate_data.output_profile(profile.clone(), output)?;
+                crate_features(
+                    &cli_features,
+                    &crates,
+                    crate_data,
+                    &configured,
+                    workspace_mode,
+                )?;
+                check_profile(crate_data, &configured)?;
+            }
+        }
+        let build = Build {
             crate_path,
-            crate_data,
-            weak_refs: build_opts.weak_refs,
-            reference_types: build_opts.reference_types,
+            crates,
+            cargo_options: build::CargoOptions {
+                toolchain: build_opts.toolchain.clone(),
+                target: target.clone(),
+                packages,
+                features,
+                no_default_features: build_opts.no_default_features,
+                all_features: build_opts.all_features,
+                rustflags,
+                lib: true,
+                examples: Vec::new(),
+                as_cdylib: false,
+            },
+            weak_refs: cli_switch(build_opts.weak_refs, build_opts.no_weak_refs),
+            reference_types: cli_switch(build_opts.reference_types, build_opts.no_reference_types),
             no_opt: build_opts.no_opt,
             profile,
-            mode: build_opts.mode,
-            out_dir,
+            mode,
+            target,
+            as_cdylib: build_opts.cdylib,
+            examples: build_opts.examples.clone(),
+            cli_features,
+            out_dirs,
             out_name: build_opts.out_name.clone(),
-            bindgen: None,
             cache: get_install_cache(&args.install_cache)?,
             extra_options: extra_options,
-        })
+        };
+        if args.verbosity > 0 {
+            build.print_settings(build_opts)?;
+        }
+        Ok(build)
+    }
+
+    /// Print the resolved profile settings of each crate, and where each
+    /// value came from.
+    fn print_settings(&self, build_opts: &BuildOptions) -> Result<()> {
+        for crate_data in &self.crates {
+            PBAR.info(&format!(
+                "Settings for {} ({} profile):",
+                crate_data.package_name(),
+                self.profile
+            ));
+            let mut settings = self.build_settings(build_opts, crate_data);
+            settings.extend(self.configured_profile(crate_data)?.settings());
+            print_setting_lines(settings);
+            for output in crate_data.outputs() {
+                PBAR.info(&format!(
+                    "Settings for the `{}` output of {}:",
+                    output.out_name(),
+                    crate_data.package_name()
+                ));
+                print_setting_lines(self.output_profile(crate_data, output)?.settings());
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the profile settings for `crate_data`, with the overrides from the
+    /// command line applied.
+    fn configured_profile(
+        &self,
+        crate_data: &manifest::CrateData,
+    ) -> Result<manifest::CargoWasmPackProfile> {
+        let mut profile = crate_data.configured_profile(self.profile.clone())?;
+        if self.no_opt {
+            profile.disable_wasm_opt("--no-opt");
+        }
+        Ok(profile)
+    }
+
+    /// Get the settings of `output` for `crate_data`, with the overrides from
+    /// the command line applied.
+    fn output_profile(
+        &self,
+        crate_data: &manifest::CrateData,
+        output: &manifest::CargoWasmPackOutput,
+    ) -> Result<manifest::CargoWasmPackProfile> {
+        let mut profile = crate_data.output_profile(self.profile.clone(), output)?;
+        if self.no_opt {
+            profile.disable_wasm_opt("--no-opt");
+        }
+        Ok(profile)
+    }
+
+    /// Get the settings outside of the profile for `crate_data`, as
+    /// `key = value` pairs in TOML syntax, along with where each value came
+    /// from.
+    fn build_settings(
+        &self,
+        build_opts: &BuildOptions,
+        crate_data: &manifest::CrateData,
+    ) -> Vec<(&'static str, String, SettingSource)> {
+        fn source<T>(
+            on_command_line: bool,
+            flag: &str,
+            configured: Option<(T, SettingSource)>,
+        ) -> SettingSource {
+            match configured {
+                _ if on_command_line => SettingSource::CommandLine(flag.to_string()),
+                Some((_, source)) => source,
+                None => SettingSource::Default,
+            }
+        }
+        let out_name = crate_data
+            .out_name()
+            .map(|out_name| (out_name, SettingSource::Package(None)));
+        vec![
+            (
+                "out-dir",
+                format!("{:?}", self.out_dir(crate_data)),
+                self.out_dirs[crate_data.package_name()].1.clone(),
+            ),
+            (
+                "out-name",
+                format!("{:?}", crate_data.name_prefix()),
+                source(build_opts.out_name.is_some(), "--out-name", out_name),
+            ),
+            (
+                "mode",
+                format!("\"{}\"", self.mode),
+                source(build_opts.mode.is_some(), "--mode", crate_data.mode()),
+            ),
+            (
+                "target",
+                format!("{:?}", self.target),
+                source(build_opts.target.is_some(), "--target", crate_data.target()),
+            ),
+            (
+                "cdylib",
+                self.as_cdylib(crate_data).to_string(),
+                source(build_opts.cdylib, "--cdylib", crate_data.cdylib()),
+            ),
+            (
+                "weak-refs",
+                self.weak_refs(crate_data).to_string(),
+                source(
+                    self.weak_refs.is_some(),
+                    if build_opts.no_weak_refs {
+                        "--no-weak-refs"
+                    } else {
+                        "--weak-refs"
+                    },
+                    crate_data.weak_refs(),
+                ),
+            ),
+            (
+                "reference-types",
+                self.reference_types(crate_data).to_string(),
+                source(
+                    self.reference_types.is_some(),
+                    if build_opts.no_reference_types {
+                        "--no-reference-types"
+                    } else {
+                        "--reference-types"
+                    },
+                    crate_data.reference_types(),
+                ),
+            ),
+        ]
+    }
+
+    /// Get the directory that the output files of `crate_data` are written
+    /// to.
+    fn out_dir(&self, crate_data: &manifest::CrateData) -> PathBuf {
+        self.out_dirs[crate_data.package_name()].0.clone()
+    }
+
+    /// Get the output directories, each with the crates that are written to it.
+    fn crates_by_out_dir(&self) -> Vec<(PathBuf, Vec<&manifest::CrateData>)> {
+        let mut out_dirs: Vec<(PathBuf, Vec<&manifest::CrateData>)> = Vec::new();
+        for crate_data in &self.crates {
+            let out_dir = self.out_dir(crate_data);
+            match out_dirs.iter_mut().find(|(dir, _)| *dir == out_dir) {
+                Some((_, crates)) => crates.push(crate_data),
+                None => out_dirs.push((out_dir, vec![crate_data])),
+            }
+        }
+        out_dirs
+    }
+
+    fn as_cdylib(&self, crate_data: &manifest::CrateData) -> bool {
+        self.as_cdylib || crate_data.cdylib().is_some_and(|(value, _)| value)
+    }
+
+    /// Whether the library of `crate_data` is built. With `--example`, only
+    /// libraries that can be compiled to WebAssembly are built along with
+    /// the examples.
+    fn builds_lib(&self, crate_data: &manifest::CrateData) -> bool {
+        self.examples.is_empty() || self.as_cdylib(crate_data) || crate_data.has_cdylib()
+    }
+
+    fn weak_refs(&self, crate_data: &manifest::CrateData) -> bool {
+        self.weak_refs
+            .unwrap_or_else(|| crate_data.weak_refs().is_some_and(|(value, _)| value))
+    }
+
+    fn reference_types(&self, crate_data: &manifest::CrateData) -> bool {
+        self.reference_types
+            .unwrap_or_else(|| crate_data.reference_types().is_some_and(|(value, _)| value))
     }
 
     /// Configures the global binary cache used for this build
@@ -190,76 +687,340 @@ impl Build {
             self.step_check_for_wasm_target()?;
         }
 
-        self.step_build_wasm()?;
+        let modules = self.step_build_wasm()?;
         self.step_create_dir()?;
-        self.step_install_wasm_bindgen()?;
-        let temp_dir = self.step_run_wasm_bindgen()?;
+        self.step_write_runtime(&modules)?;
+
+        for module in &modules {
+            if modules.len() > 1 {
+                PBAR.info(&format!("Generating Javascript for {}...", module));
+            }
+            let bindgen = self.step_install_wasm_bindgen(module.crate_data)?;
+            let temp_dir = module.crate_data.target_directory().join("wasm-bindgen");
+            self.step_run_wasm_bindgen(
+                module,
+                &bindgen,
+                &module.profile,
+                &module.wasm_path,
+                &temp_dir,
+            )?;
+            self.step_run_wasm_snip(module, &module.profile, &temp_dir)?;
+            self.step_pre_initialize_wasm(module, &module.profile, &temp_dir)?;
+            self.step_run_wasm_opt(module, &module.profile, &temp_dir)?;
+            self.step_strip_sections(module, &module.profile, &temp_dir)?;
+            self.step_post_process(module, &module.profile, &temp_dir)?;
+
+            let enhanced_dir = match &module.enhanced {
+                Some(enhanced) => {
+                    let enhanced_dir = module
+                        .crate_data
+                        .target_directory()
+                        .join("wasm-bindgen-enhanced");
+                    self.step_run_wasm_bindgen(
+                        module,
+                        &bindgen,
+                        &enhanced.profile,
+                        &enhanced.wasm_path,
+                        &enhanced_dir,
+                    )?;
+                    self.step_run_wasm_snip(module, &enhanced.profile, &enhanced_dir)?;
+                    self.step_pre_initialize_wasm(module, &enhanced.profile, &enhanced_dir)?;
+                    self.step_run_wasm_opt(module, &enhanced.profile, &enhanced_dir)?;
+                    self.step_strip_sections(module, &enhanced.profile, &enhanced_dir)?;
+                    self.step_post_process(module, &enhanced.profile, &enhanced_dir)?;
+                    self.step_check_enhanced_bindings(module, &temp_dir, &enhanced_dir)?;
+                    Some(enhanced_dir)
+                }
+                None => None,
+            };
 
-        if !self.no_opt {
-            self.step_run_wasm_opt()?;
+            self.step_run_wasm2js(module, &temp_dir)?;
+            self.step_transform_wasm(module, &temp_dir, enhanced_dir.as_deref())?;
         }
-        self.step_transform_wasm(&temp_dir)?;
 
         let duration = elapsed(started.elapsed());
         info!("Done in {}.", &duration);
-        info!("Javascript files created in {}.", self.out_dir.display());
 
         PBAR.info(&format!("Done in {}", &duration));
 
-        PBAR.info(&format!(
-            "Javascript files created in {}.",
-            self.out_dir.display()
-        ));
+        for (out_dir, _) in self.crates_by_out_dir() {
+            info!("Javascript files created in {}.", out_dir.display());
+            PBAR.info(&format!(
+                "Javascript files created in {}.",
+                out_dir.display()
+            ));
+            if modules.len() > 1 {
+                for module in &modules {
+                    if self.out_dir(module.crate_data) == out_dir {
+                        PBAR.info(&format!("  {}: {}.js", module, module.name_prefix()));
+                    }
+                }
+            }
+        }
         Ok(())
     }
 
     fn step_check_rustc_version(&mut self) -> Result<()> {
         info!("Checking rustc version...");
-        let version = build::check_rustc_version()?;
+        let version =
+            build::check_rustc_version(&self.crate_path, self.cargo_options.toolchain.as_deref())?;
         let msg = format!("rustc version is {}.", version);
         info!("{}", &msg);
+        for crate_data in &self.crates {
+            crate_data.check_rust_version(&version)?;
+        }
         Ok(())
     }
 
     fn step_check_crate_config(&mut self) -> Result<()> {
         info!("Checking crate configuration...");
-        self.crate_data.check_crate_config()?;
+        for crate_data in self.crates.iter().filter(|c| self.builds_lib(c)) {
+            crate_data.check_crate_config(self.as_cdylib(crate_data))?;
+        }
         info!("Crate is correctly configured.");
         Ok(())
     }
 
     fn step_check_for_wasm_target(&mut self) -> Result<()> {
         info!("Checking for wasm-target...");
-        build::wasm_target::check_for_wasm32_target()?;
+        build::wasm_target::check_for_wasm32_target(
+            &self.target,
+            &self.crate_path,
+            self.cargo_options.toolchain.as_deref(),
+        )?;
         info!("Checking for wasm-target was successful.");
         Ok(())
     }
 
-    fn step_build_wasm(&mut self) -> Result<()> {
+    fn step_build_wasm(&self) -> Result<Vec<WasmModule<'_>>> {
         info!("Building wasm...");
-        build::cargo_build_wasm(&self.crate_path, self.profile.clone(), &self.extra_options)?;
-
-        info!(
-            "wasm built at {:#?}.",
-            &self
-                .crate_path
-                .join("target")
-                .join("wasm32-unknown-unknown")
-                .join("release")
-        );
+        // Libraries with `output` entries are built separately for each of
+        // them
+        let (as_cdylib, as_declared): (Vec<_>, Vec<_>) = self
+            .crates
+            .iter()
+            .filter(|crate_data| self.builds_lib(crate_data) && crate_data.outputs().is_empty())
+            .partition(|crate_data| self.as_cdylib(crate_data));
+        let mut artifacts = Vec::new();
+        if !as_declared.is_empty() || !self.examples.is_empty() {
+            let mut options = self.cargo_options.clone();
+            options.lib = !as_declared.is_empty();
+            options.examples = self.examples.clone();
+            if !options.packages.is_empty() {
+                let packages: Vec<&str> = self
+                    .crates
+                    .iter()
+                    .filter(|crate_data| {
+                        as_declared
+                            .iter()
+                            .any(|c| c.package_id() == crate_data.package_id())
+                            || self
+                                .examples
+                                .iter()
+                                .any(|example| crate_data.has_example(example))
+                    })
+                    .map(|crate_data| crate_data.package_name())
+                    .collect();
+                options.features.retain(|feature| {
+                    !is_member_feature(&self.crates, feature)
+                        || feature
+                            .split_once('/')
+                            .is_some_and(|(package, _)| packages.contains(&package))
+                });
+                options.packages = packages.into_iter().map(String::from).collect();
+            }
+            artifacts.extend(build::cargo_build_wasm(
+                &self.crate_path,
+                self.profile.clone(),
+                &options,
+                &self.extra_options,
+            )?);
+        }
+        // `cargo rustc` builds one package at a time
+        for crate_data in &as_cdylib {
+            let name = crate_data.package_name();
+            let mut options = self.cargo_options.clone();
+            options.as_cdylib = true;
+            if !options.packages.is_empty() {
+                let prefix = format!("{}/", name);
+                options.packages = vec![name.to_string()];
+                options.features.retain(|feature| {
+                    feature.starts_with(&prefix) || !is_member_feature(&self.crates, feature)
+                });
+            }
+            artifacts.extend(build::cargo_build_wasm(
+                &self.crate_path,
+                self.profile.clone(),
+                &options,
+                &self.extra_options,
+            )?);
+        }
+
+        let mut modules = Vec::new();
+        for crate_data in &self.crates {
+            let examples = self
+                .examples
+                .iter()
+                .filter(|example| crate_data.has_example(example))
+                .map(|example| Some(example.as_str()));
+            let lib =
+                (self.builds_lib(crate_data) && crate_data.outputs().is_empty()).then_some(None);
+            for example in lib.into_iter().chain(examples) {
+                let mut module = WasmModule {
+                    crate_data,
+                    example,
+                    output: None,
+                    profile: self.configured_profile(crate_data)?,
+                    wasm_path: PathBuf::new(),
+                    enhanced: None,
+                };
+                let built =
+                    build::find_wasm_artifact(&artifacts, crate_data.package_id(), example)?;
+                module.wasm_path = keep_artifact(crate_data, &built, &module.name_prefix())?;
+                modules.push(module);
+            }
+        }
+
+        // Outputs and enhanced variants are built one module at a time, but
+        // share a cargo build when they compile with the same options
+        let mut builds = Vec::new();
+        for crate_data in &self.crates {
+            if !self.builds_lib(crate_data) {
+                continue;
+            }
+            for output in crate_data.outputs() {
+                let mut module = WasmModule {
+                    crate_data,
+                    example: None,
+                    output: Some(output),
+                    profile: self.output_profile(crate_data, output)?,
+                    wasm_path: PathBuf::new(),
+                    enhanced: None,
+                };
+                module.wasm_path = self.build_module(
+                    &mut builds,
+                    &module,
+                    &module.profile,
+                    &module.name_prefix(),
+                )?;
+                modules.push(module);
+            }
+        }
+        for module in &mut modules {
+            if let Some(profile) = module.profile.enhanced_profile() {
+                let name = format!("{}_enhanced", module.name_prefix());
+                let wasm_path = self.build_module(&mut builds, module, &profile, &name)?;
+                module.enhanced = Some(WasmVariant { profile, wasm_path });
+            }
+        }
+        Ok(modules)
+    }
+
+    /// Build the library or example of `module` on its own, with the features
+    /// and `rustc` flags of `profile`, unless one of `builds` was made with the
+    /// same options.
+    fn build_module(
+        &self,
+        builds: &mut Vec<(build::CargoOptions, Vec<Artifact>)>,
+        module: &WasmModule,
+        profile: &manifest::CargoWasmPackProfile,
+        name: &str,
+    ) -> Result<PathBuf> {
+        let crate_data = module.crate_data;
+        let workspace_mode = !self.cargo_options.packages.is_empty();
+        let mut options = self.cargo_options.clone();
+        match module.example {
+            Some(example) => {
+                options.lib = false;
+                options.examples = vec![example.to_string()];
+            }
+            None => options.as_cdylib = self.as_cdylib(crate_data),
+        }
+        if workspace_mode {
+            options.packages = vec![crate_data.package_name().to_string()];
+        }
+        options.features = crate_features(
+            &self.cli_features,
+            &self.crates,
+            crate_data,
+            profile,
+            workspace_mode,
+        )?;
+        options.rustflags = profile.rustc_flags();
+
+        let index = match builds.iter().position(|(built, _)| *built == options) {
+            Some(index) => index,
+            None => {
+                let artifacts = build::cargo_build_wasm(
+                    &self.crate_path,
+                    self.profile.clone(),
+                    &options,
+                    &self.extra_options,
+                )?;
+                builds.push((options, artifacts));
+                builds.len() - 1
+            }
+        };
+        let built =
+            build::find_wasm_artifact(&builds[index].1, crate_data.package_id(), module.example)?;
+        keep_artifact(crate_data, &built, name)
+    }
+
+    fn step_create_dir(&self) -> Result<()> {
+        for crate_data in &self.crates {
+            if let (out_dir, SettingSource::JsProject(setting)) =
+                &self.out_dirs[crate_data.package_name()]
+            {
+                PBAR.info(&format!(
+                    "Using the output directory {} from {}.",
+                    out_dir.display(),
+                    setting
+                ));
+            }
+        }
+        for (out_dir, _) in self.crates_by_out_dir() {
+            info!("Creating a dist directory...");
+            create_output_dir(&out_dir)?;
+            info!("Created a dist directory at {:#?}.", &out_dir);
+        }
         Ok(())
     }
 
-    fn step_create_dir(&mut self) -> Result<()> {
-        info!("Creating a dist directory...");
-        create_output_dir(&self.out_dir)?;
-        info!("Created a dist directory at {:#?}.", &self.crate_path);
+    fn step_write_runtime(&self, modules: &[WasmModule]) -> Result<()> {
+        for (out_dir, _) in self.crates_by_out_dir() {
+            // Every module in the output directory shares the runtime, so it
+            // has to work for all of them, including the ones that earlier
+            // builds left there
+            let runtime_path = out_dir.join(RUNTIME_FILENAME);
+            let mut options = fs::read_to_string(&runtime_path)
+                .ok()
+                .and_then(|runtime| js_bin::runtime_options(&runtime))
+                .unwrap_or_default();
+            for module in modules {
+                if self.out_dir(module.crate_data) == out_dir {
+                    options = options.merge(RuntimeOptions {
+                        target: module.profile.loader_target(),
+                        snapshot: module.profile.snapshot(),
+                    });
+                }
+            }
+            info!(
+                "Writing the {} runtime to {:#?}...",
+                options.target, &out_dir
+            );
+            let mut outfile = File::create(runtime_path)?;
+            js_bin::write_runtime(BufWriter::new(&mut outfile), options)?;
+            outfile.sync_all()?;
+        }
         Ok(())
     }
 
-    fn step_install_wasm_bindgen(&mut self) -> Result<()> {
+    fn step_install_wasm_bindgen(
+        &self,
+        crate_data: &manifest::CrateData,
+    ) -> Result<install::Status> {
         info!("Identifying wasm-bindgen dependency...");
-        let lockfile = Lockfile::new(&self.crate_data)?;
+        let lockfile = Lockfile::new(crate_data)?;
         let bindgen_version = lockfile.require_wasm_bindgen()?;
         info!("Installing wasm-bindgen-cli...");
         let bindgen = install::download_prebuilt_or_cargo_install(
@@ -268,78 +1029,303 @@ impl Build {
             bindgen_version,
             self.mode.install_permitted(),
         )?;
-        self.bindgen = Some(bindgen);
         info!("Installing wasm-bindgen-cli was successful.");
-        Ok(())
+        Ok(bindgen)
     }
 
-    fn step_run_wasm_bindgen(&mut self) -> Result<PathBuf> {
+    fn step_run_wasm_bindgen(
+        &self,
+        module: &WasmModule,
+        bindgen: &install::Status,
+        profile: &manifest::CargoWasmPackProfile,
+        wasm_path: &Path,
+        temp_dir: &Path,
+    ) -> Result<()> {
         info!("Building the wasm bindings...");
-        let temp_dir = bindgen::wasm_bindgen_build(
-            &self.crate_data,
-            self.bindgen.as_ref().unwrap(),
-            &self.out_name,
-            self.weak_refs,
-            self.reference_types,
-            self.profile.clone(),
-            &self.extra_options,
+        let crate_data = module.crate_data;
+        bindgen::wasm_bindgen_build(
+            bindgen,
+            wasm_path,
+            temp_dir,
+            &module.out_name(),
+            self.weak_refs(crate_data),
+            self.reference_types(crate_data),
+            profile,
         )?;
-        info!("wasm bindings were built at {:#?}.", &temp_dir);
-        Ok(temp_dir)
+        info!("wasm bindings were built at {:#?}.", temp_dir);
+        Ok(())
     }
 
-    fn step_run_wasm_opt(&mut self) -> Result<()> {
-        let mut args = match self
-            .crate_data
-            .configured_profile(self.profile.clone())
-            .wasm_opt_args()
-        {
+    fn step_run_wasm_snip(
+        &self,
+        module: &WasmModule,
+        profile: &manifest::CargoWasmPackProfile,
+        temp_dir: &Path,
+    ) -> Result<()> {
+        if !profile.wasm_snip() {
+            return Ok(());
+        }
+        let wasm_path = temp_dir.join(format!("{}_bg.wasm", module.name_prefix()));
+        info!("Snipping {:?}...", &wasm_path);
+        wasm_snip::run(
+            &self.cache,
+            &wasm_path,
+            profile.wasm_snip_patterns(),
+            profile.wasm_snip_rust_panicking_code(),
+            profile.wasm_snip_rust_fmt_code(),
+            self.mode.install_permitted(),
+        ).map_err(|e| {
+            anyhow!(
+                "{}\nTo disable `wasm-snip`, remove the `wasm-snip` settings from your package metadata in your `Cargo.toml`.", e
+            )
+        })
+    }
+
+    fn step_pre_initialize_wasm(
+        &self,
+        module: &WasmModule,
+        profile: &manifest::CargoWasmPackProfile,
+        temp_dir: &Path,
+    ) -> Result<()> {
+        if !profile.pre_initialize() {
+            return Ok(());
+        }
+        let wasm_path = temp_dir.join(format!("{}_bg.wasm", module.name_prefix()));
+        info!("Pre-initializing {:?}...", &wasm_path);
+        pre_initialize::run(&self.cache, &wasm_path, self.mode.install_permitted()).map_err(|e| {
+            anyhow!(
+                "{:#}\nTo disable pre-initialization, remove `pre-initialize = true` from your package metadata in your `Cargo.toml`.", e
+            )
+        })
+    }
+
+    fn step_run_wasm_opt(
+        &self,
+        module: &WasmModule,
+        profile: &manifest::CargoWasmPackProfile,
+        temp_dir: &Path,
+    ) -> Result<()> {
+        let crate_data = module.crate_data;
+        // With `wasm-opt = "auto"`, the candidate flags come first
+        let mut args = match profile.wasm_opt_args() {
+            Some(_) if profile.wasm_opt_auto() => Vec::new(),
             Some(args) => args,
             None => return Ok(()),
         };
-        if self.reference_types {
+        if self.reference_types(crate_data) {
             args.push("--enable-reference-types".into());
         }
-        info!("executing wasm-opt with {:?}", args);
-        wasm_opt::run(
+        // Keep the name section, so profilers can show function names
+        if matches!(self.profile, BuildProfile::Profiling) {
+            args.push("-g".into());
+        }
+        args.extend(wasm_opt::feature_args(profile.target_features()));
+        let wasm_path = temp_dir.join(format!("{}_bg.wasm", module.name_prefix()));
+        let result = if profile.wasm_opt_auto() {
+            info!("tuning wasm-opt with {:?}", args);
+            wasm_opt::tune(
+                &self.cache,
+                &wasm_path,
+                wasm_opt::AUTO_CANDIDATES,
+                &args,
+                profile.compression_level(),
+                self.mode.install_permitted(),
+            )
+            .map(|trials| {
+                if let Some(trials) = trials {
+                    print_wasm_opt_trials(module, &trials);
+                }
+            })
+        } else {
+            info!("executing wasm-opt with {:?}", args);
+            wasm_opt::run(
+                &self.cache,
+                &wasm_path,
+                &args,
+                self.mode.install_permitted(),
+            )
+        };
+        result.map_err(|e| {
+            anyhow!(
+                "{}\nTo disable `wasm-opt`, add `wasm-opt = false` to your package metadata in your `Cargo.toml`.", e
+            )
+        })
+    }
+
+    fn step_strip_sections(
+        &self,
+        module: &WasmModule,
+        profile: &manifest::CargoWasmPackProfile,
+        temp_dir: &Path,
+    ) -> Result<()> {
+        if profile.keep_sections().is_empty() && profile.strip_sections().is_empty() {
+            return Ok(());
+        }
+        let wasm_path = temp_dir.join(format!("{}_bg.wasm", module.name_prefix()));
+        info!("Removing custom sections from {:?}...", &wasm_path);
+        let removed = custom_sections::strip_file(
+            &wasm_path,
+            profile.keep_sections(),
+            profile.strip_sections(),
+        )?;
+        for section in removed {
+            PBAR.info(&format!(
+                "Removed the `{}` custom section from {}, saving {} bytes.",
+                section.name, module, section.size
+            ));
+        }
+        Ok(())
+    }
+
+    fn step_post_process(
+        &self,
+        module: &WasmModule,
+        profile: &manifest::CargoWasmPackProfile,
+        temp_dir: &Path,
+    ) -> Result<()> {
+        if profile.post_process().is_empty() {
+            return Ok(());
+        }
+        let crate_data = module.crate_data;
+        let out_name = module.name_prefix();
+        let wasm_path = temp_dir.join(format!("{}_bg.wasm", out_name));
+        info!("Post-processing {:?}...", &wasm_path);
+        post_process::run(
+            profile.post_process(),
+            &post_process::Input {
+                wasm_path: &wasm_path,
+                package_dir: crate_data.manifest_dir(),
+                package_name: crate_data.package_name(),
+                out_name: &out_name,
+            },
+        )
+    }
+
+    /// Check that the enhanced variant in `enhanced_dir` has the same bindings
+    /// as the baseline in `temp_dir`, since they share the generated
+    /// Javascript and types.
+    fn step_check_enhanced_bindings(
+        &self,
+        module: &WasmModule,
+        temp_dir: &Path,
+        enhanced_dir: &Path,
+    ) -> Result<()> {
+        let name_prefix = module.name_prefix();
+        for filename in [
+            format!("{}_bg.js", name_prefix),
+            format!("{}.d.ts", name_prefix),
+        ] {
+            if fs::read(temp_dir.join(&filename))? != fs::read(enhanced_dir.join(&filename))? {
+                bail!(
+                    "The enhanced variant of {} has different bindings than the baseline, so they can't share {}. Make sure that `#[wasm_bindgen]` items don't depend on target features.",
+                    module,
+                    filename
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn step_run_wasm2js(&self, module: &WasmModule, temp_dir: &Path) -> Result<()> {
+        let crate_data = module.crate_data;
+        let profile = &module.profile;
+        if !profile.wasm2js() {
+            return Ok(());
+        }
+        let args = profile.wasm_opt_args().unwrap_or_default();
+        let name_prefix = module.name_prefix();
+        let wasm_path = temp_dir.join(format!("{}_bg.wasm", name_prefix));
+        let out_path = self
+            .out_dir(crate_data)
+            .join(format!("{}_wasm2js.js", name_prefix));
+        info!("executing wasm2js with {:?}", args);
+        wasm2js::run(
             &self.cache,
-            &self.out_dir,
+            &wasm_path,
+            &out_path,
             &args,
             self.mode.install_permitted(),
-        ).map_err(|e| {
+        )
+        .map_err(|e| {
             anyhow!(
-                "{}\nTo disable `wasm-opt`, add `wasm-opt = false` to your package metadata in your `Cargo.toml`.", e
+                "{}\nTo disable the Javascript fallback, remove `wasm2js = true` from your package metadata in your `Cargo.toml`.", e
             )
         })
     }
 
-    fn step_transform_wasm(&mut self, temp_dir: &Path) -> Result<()> {
-        let name_prefix = self.crate_data.name_prefix();
+    fn step_transform_wasm(
+        &self,
+        module: &WasmModule,
+        temp_dir: &Path,
+        enhanced_dir: Option<&Path>,
+    ) -> Result<()> {
+        let crate_data = module.crate_data;
+        let out_dir = self.out_dir(crate_data);
+        let name_prefix = module.name_prefix();
         let wasm_filename = format!("{}_bg.wasm", name_prefix);
         let imports_filename = format!("{}_bg.js", name_prefix);
         let types_filename = format!("{}.d.ts", name_prefix);
         let module_filename = format!("{}.js", name_prefix);
         let imports_module = format!("./{}", imports_filename);
+        let profile = &module.profile;
+        let snapshot = profile.snapshot();
+        let mut loader_options = LoaderOptions {
+            snapshot,
+            wasm2js_module: if profile.wasm2js() {
+                Some(format!("./{}_wasm2js.js", name_prefix))
+            } else {
+                None
+            },
+            ..Default::default()
+        };
+        // The enhanced variant is embedded, and the baseline is imported when
+        // the engine lacks any of the enhanced features
+        let input_path = match enhanced_dir {
+            Some(enhanced_dir) => {
+                let baseline_filename = format!("{}_baseline.js", name_prefix);
+                let mut outfile = File::create(out_dir.join(&baseline_filename))?;
+                {
+                    let mut outbw = BufWriter::new(&mut outfile);
+                    let mut wasm_writer = WasmJsWriter::chunks_only(&mut outbw);
+                    read_and_compress(
+                        &mut wasm_writer,
+                        &temp_dir.join(&wasm_filename),
+                        profile.compression_level(),
+                    )?;
+                    wasm_writer.flush()?;
+                }
+                outfile.sync_all()?;
+                loader_options.baseline_module = Some(format!("./{}", baseline_filename));
+                loader_options.feature_probes = profile
+                    .enhanced_target_features()
+                    .iter()
+                    .filter_map(|feature| js_bin::feature_probe(feature.trim_start_matches('+')))
+                    .collect();
+                enhanced_dir.join(&wasm_filename)
+            }
+            None => temp_dir.join(&wasm_filename),
+        };
+        loader_options.build_id = build_id(&input_path)?;
         // convert wasm to JS
         {
-            let mut outfile = File::create(self.out_dir.join(module_filename))?;
+            let mut outfile = File::create(out_dir.join(module_filename))?;
             {
                 let mut outbw = BufWriter::new(&mut outfile);
-                let mut wasm_writer = WasmJsWriter::new(&mut outbw, &imports_module);
-                let input_path = temp_dir.join(wasm_filename);
-                read_and_compress(&mut wasm_writer, &input_path)?;
+                let mut wasm_writer =
+                    WasmJsWriter::new(&mut outbw, &imports_module, loader_options);
+                read_and_compress(&mut wasm_writer, &input_path, profile.compression_level())?;
                 wasm_writer.flush()?;
             }
             outfile.sync_all()?;
         }
         fs::copy(
             temp_dir.join(&imports_filename),
-            self.out_dir.join(&imports_filename),
+            out_dir.join(&imports_filename),
         )?;
         // transform types file
         {
             let types_text = fs::read(temp_dir.join(&types_filename))?;
-            let mut outfile = File::create(self.out_dir.join(&types_filename))?;
+            let mut outfile = File::create(out_dir.join(&types_filename))?;
             {
                 let mut outbw = BufWriter::new(&mut outfile);
                 outbw.write_all(
@@ -353,18 +1339,214 @@ impl Build {
                         .to_os_bytes()
                         .as_ref(),
                 )?;
+                if snapshot {
+                    outbw.write_all(
+                        "export function snapshotWasm(): Uint8Array;\nexport function restoreWasm(bytes: Uint8Array | ArrayBuffer): Promise<WasmExports>;\n"
+                            .to_os_bytes()
+                            .as_ref(),
+                    )?;
+                }
                 outbw.flush()?;
             }
             outfile.sync_all()?;
         }
 
-        for file in self.out_dir.read_dir()? {
-            let file = file?;
-            let path = file.path();
-            let extension = path.extension().and_then(|s| s.to_str());
-            if extension == Some("wasm") {}
+        Ok(())
+    }
+}
+
+/// Get the value of a setting that `get` reads for each of `crates`, which are
+/// built in one go, so they have to agree on it.
+fn agreed_setting<T: PartialEq>(
+    crates: &[manifest::CrateData],
+    key: &str,
+    get: impl Fn(&manifest::CrateData) -> T,
+) -> Result<T> {
+    let value = get(&crates[0]);
+    for crate_data in &crates[1..] {
+        if get(crate_data) != value {
+            bail!(
+                "`{}` must be the same for all packages that are built together, but {} and {} differ.",
+                key,
+                crates[0].package_name(),
+                crate_data.package_name()
+            );
         }
+    }
+    Ok(value)
+}
 
-        Ok(())
+/// Whether `feature` is qualified with the name of one of `crates`, as
+/// `member/feature`.
+fn is_member_feature(crates: &[manifest::CrateData], feature: &str) -> bool {
+    feature.split_once('/').is_some_and(|(package, _)| {
+        crates
+            .iter()
+            .any(|crate_data| crate_data.package_name() == package)
+    })
+}
+
+/// Print `key = value  # source` lines, with the sources lined up.
+fn print_setting_lines(settings: Vec<(&'static str, String, SettingSource)>) {
+    let width = settings
+        .iter()
+        .map(|(key, value, _)| key.len() + value.len())
+        .max()
+        .unwrap_or(0);
+    for (key, value, source) in settings {
+        PBAR.info(&format!(
+            "  {} = {:pad$}  # {}",
+            key,
+            value,
+            source,
+            pad = width - key.len()
+        ));
+    }
+}
+
+/// Print the compressed sizes that `wasm-opt = "auto"` got for `module` with
+/// each set of flags, smallest first.
+fn print_wasm_opt_trials(module: &WasmModule, trials: &[wasm_opt::Trial]) {
+    PBAR.info(&format!(
+        "Compressed sizes of {} with each set of `wasm-opt` flags:",
+        module
+    ));
+    let flags: Vec<String> = trials.iter().map(|trial| trial.flags.join(" ")).collect();
+    let width = flags.iter().map(String::len).max().unwrap_or(0);
+    for (i, (trial, flags)) in trials.iter().zip(&flags).enumerate() {
+        PBAR.info(&format!(
+            "  {:width$}  {:>9} bytes{}",
+            flags,
+            trial.compressed_size,
+            if i == 0 { "  (kept)" } else { "" },
+            width = width
+        ));
     }
 }
+
+/// Get the features to activate for `crate_data`: the ones from the command
+/// line that apply to it, and the `features` of its `profile`. When
+/// `workspace_mode` is set, they are qualified with the package name.
+fn crate_features(
+    cli_features: &[String],
+    crates: &[manifest::CrateData],
+    crate_data: &manifest::CrateData,
+    profile: &manifest::CargoWasmPackProfile,
+    workspace_mode: bool,
+) -> Result<Vec<String>> {
+    let name = crate_data.package_name();
+    // In a workspace, `member/feature` only applies to that member, and
+    // everything else applies to all of the selected members.
+    let mut features: Vec<String> = cli_features
+        .iter()
+        .filter_map(|feature| match feature.split_once('/') {
+            Some((package, feature))
+                if workspace_mode && crates.iter().any(|c| c.package_name() == package) =>
+            {
+                (package == name).then(|| feature.to_string())
+            }
+            _ => Some(feature.clone()),
+        })
+        .collect();
+    features.extend(profile.features().iter().cloned());
+    crate_data.check_features(&features)?;
+    Ok(features
+        .into_iter()
+        .map(|feature| {
+            if workspace_mode && !feature.contains('/') {
+                format!("{}/{}", name, feature)
+            } else {
+                feature
+            }
+        })
+        .collect())
+}
+
+/// The value of a setting that has a flag to turn it on and one to turn it
+/// off, or `None` if neither is on the command line.
+fn cli_switch(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// Check that the settings of `profile` for `crate_data` work together.
+fn check_profile(
+    crate_data: &manifest::CrateData,
+    profile: &manifest::CargoWasmPackProfile,
+) -> Result<()> {
+    if profile.wasm2js() && profile.loader_target() < LoaderTarget::Es2020 {
+        bail!(
+            "The wasm2js fallback is loaded with dynamic `import()`, which requires `loader-target = \"es2020\"`, but the {} loader target is configured for {}.",
+            profile.loader_target(),
+            crate_data.package_name()
+        );
+    }
+    if profile.wasm2js() && profile.snapshot() {
+        bail!(
+            "`snapshot` can't be combined with `wasm2js` for {}, because a snapshot can't be restored into the wasm2js fallback.",
+            crate_data.package_name()
+        );
+    }
+    if profile.compression_level() > 9 {
+        bail!(
+            "`compression-level` must be between 0 and 9, but it is {} for {}.",
+            profile.compression_level(),
+            crate_data.package_name()
+        );
+    }
+    if profile.post_process().iter().any(Vec::is_empty) {
+        bail!(
+            "`post-process` has an empty command for {}. Each command is a list of the program and its arguments.",
+            crate_data.package_name()
+        );
+    }
+    if !profile.enhanced_target_features().is_empty() {
+        if profile.loader_target() < LoaderTarget::Es2020 {
+            bail!(
+                "The baseline of an enhanced build is loaded with dynamic `import()`, which requires `loader-target = \"es2020\"`, but the {} loader target is configured for {}.",
+                profile.loader_target(),
+                crate_data.package_name()
+            );
+        }
+        if profile.snapshot() {
+            bail!(
+                "`snapshot` can't be combined with `enhanced-target-features` for {}, because the snapshot would only cover one of the variants.",
+                crate_data.package_name()
+            );
+        }
+        for feature in profile.enhanced_target_features() {
+            if feature.starts_with('-') {
+                bail!(
+                    "`enhanced-target-features` only enables features, but it has `{}` for {}.",
+                    feature,
+                    crate_data.package_name()
+                );
+            }
+            if js_bin::feature_probe(feature.trim_start_matches('+')).is_none() {
+                bail!(
+                    "The `{}` target feature can't be detected at runtime, so it can't be in `enhanced-target-features` for {}.",
+                    feature,
+                    crate_data.package_name()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy the wasm that cargo `built` for `crate_data` to `name.wasm` in
+/// `target/wasm-js`, so that builds with other options don't overwrite it.
+fn keep_artifact(crate_data: &manifest::CrateData, built: &Path, name: &str) -> Result<PathBuf> {
+    let wasm_dir = crate_data
+        .target_directory()
+        .join("wasm-js")
+        .join(crate_data.package_name());
+    fs::create_dir_all(&wasm_dir)?;
+    let wasm_path = wasm_dir.join(format!("{}.wasm", name));
+    fs::copy(built, &wasm_path)?;
+    info!("wasm built at {:#?}.", &wasm_path);
+    Ok(wasm_path)
+}
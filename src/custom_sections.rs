@@ -0,0 +1,89 @@
+//! Removing custom sections from wasm modules before they are embedded
+
+use anyhow::{bail, Context, Result};
+use glob::Pattern;
+use std::fs;
+use std::path::Path;
+use wasmparser::BinaryReader;
+
+/// The preamble of a core wasm module: the magic number and version 1.
+pub(crate) const MODULE_HEADER: &[u8] = b"\0asm\x01\0\0\0";
+
+/// The section id of custom sections.
+const CUSTOM_SECTION_ID: u8 = 0;
+
+/// A custom section that [strip] removed.
+pub struct RemovedSection {
+    /// The name of the section.
+    pub name: String,
+    /// The bytes that removing it saved, including the section header.
+    pub size: usize,
+}
+
+/// Remove the custom sections of the wasm module `wasm` whose names match one
+/// of the `strip` patterns, or, when `keep` isn't empty, don't match any of the
+/// `keep` patterns. Patterns are globs, so `.debug_*` matches all of the DWARF
+/// sections.
+///
+/// Returns the rewritten module, and the sections that were removed.
+pub fn strip(
+    wasm: &[u8],
+    keep: &[String],
+    strip: &[String],
+) -> Result<(Vec<u8>, Vec<RemovedSection>)> {
+    let keep = patterns(keep, "keep-sections")?;
+    let strip = patterns(strip, "strip-sections")?;
+    if !wasm.starts_with(MODULE_HEADER) {
+        bail!("only core wasm modules can have their custom sections removed");
+    }
+
+    let mut out = MODULE_HEADER.to_vec();
+    let mut removed = Vec::new();
+    let mut reader = BinaryReader::new(&wasm[MODULE_HEADER.len()..], MODULE_HEADER.len());
+    while !reader.eof() {
+        let start = reader.original_position();
+        let id = reader.read_u8()?;
+        let size = reader.read_var_u32()? as usize;
+        let contents = reader.read_bytes(size)?;
+        let section = &wasm[start..reader.original_position()];
+        if id == CUSTOM_SECTION_ID {
+            let name = BinaryReader::new(contents, 0).read_string()?;
+            let matches = |patterns: &[Pattern]| patterns.iter().any(|p| p.matches(name));
+            if matches(&strip) || (!keep.is_empty() && !matches(&keep)) {
+                removed.push(RemovedSection {
+                    name: name.to_string(),
+                    size: section.len(),
+                });
+                continue;
+            }
+        }
+        out.extend_from_slice(section);
+    }
+    Ok((out, removed))
+}
+
+/// Rewrite the wasm module at `wasm_path` without the custom sections that
+/// [strip] removes.
+pub fn strip_file(
+    wasm_path: &Path,
+    keep: &[String],
+    strip_patterns: &[String],
+) -> Result<Vec<RemovedSection>> {
+    let wasm = fs::read(wasm_path)?;
+    let (stripped, removed) = strip(&wasm, keep, strip_patterns)
+        .with_context(|| format!("Removing custom sections from {}", wasm_path.display()))?;
+    if !removed.is_empty() {
+        fs::write(wasm_path, stripped)?;
+    }
+    Ok(removed)
+}
+
+fn patterns(patterns: &[String], setting: &str) -> Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern)
+                .with_context(|| format!("`{}` has an invalid pattern `{}`", setting, pattern))
+        })
+        .collect()
+}
@@ -158,6 +158,19 @@ pub fn download_prebuilt(
                 None => Ok(Status::CannotInstall),
             }
         }
+        Tool::Wasm2Js => {
+            let binaries: &[&str] = match Os::get()? {
+                Os::MacOS => &["bin/wasm2js", "lib/libbinaryen.dylib"],
+                Os::Linux => &["bin/wasm2js"],
+                Os::Windows => &["bin/wasm2js.exe"],
+            };
+            match cache.download(install_permitted, "wasm2js", binaries, &url)? {
+                Some(download) => Ok(Status::Found(download)),
+                None => Ok(Status::CannotInstall),
+            }
+        }
+        Tool::WasmSnip => bail!("wasm-snip has no prebuilt binaries"),
+        Tool::Wizer => bail!("wizer is only installed with `cargo install`"),
     }
 }
 
@@ -172,15 +185,15 @@ fn prebuilt_url(tool: &Tool, version: &str) -> Result<String> {
 /// Get the download URL for some tool at some version, architecture and operating system
 pub fn prebuilt_url_for(tool: &Tool, version: &str, arch: &Arch, os: &Os) -> Result<String> {
     let target = match (os, arch, tool) {
-        (Os::Linux, Arch::AArch64, Tool::WasmOpt) => "aarch64-linux",
+        (Os::Linux, Arch::AArch64, Tool::WasmOpt | Tool::Wasm2Js) => "aarch64-linux",
         (Os::Linux, Arch::AArch64, _) => "aarch64-unknown-linux-gnu",
-        (Os::Linux, Arch::X86_64, Tool::WasmOpt) => "x86_64-linux",
+        (Os::Linux, Arch::X86_64, Tool::WasmOpt | Tool::Wasm2Js) => "x86_64-linux",
         (Os::Linux, Arch::X86_64, _) => "x86_64-unknown-linux-musl",
-        (Os::MacOS, Arch::X86_64, Tool::WasmOpt) => "x86_64-macos",
+        (Os::MacOS, Arch::X86_64, Tool::WasmOpt | Tool::Wasm2Js) => "x86_64-macos",
         (Os::MacOS, Arch::X86_64, _) => "x86_64-apple-darwin",
         (Os::MacOS, Arch::AArch64, Tool::CargoGenerate) => "aarch64-apple-darwin",
-        (Os::MacOS, Arch::AArch64, Tool::WasmOpt) => "arm64-macos",
-        (Os::Windows, Arch::X86_64, Tool::WasmOpt) => "x86_64-windows",
+        (Os::MacOS, Arch::AArch64, Tool::WasmOpt | Tool::Wasm2Js) => "arm64-macos",
+        (Os::Windows, Arch::X86_64, Tool::WasmOpt | Tool::Wasm2Js) => "x86_64-windows",
         (Os::Windows, Arch::X86_64, _) => "x86_64-pc-windows-msvc",
         _ => bail!("Unrecognized target!"),
     };
@@ -199,13 +212,15 @@ pub fn prebuilt_url_for(tool: &Tool, version: &str, arch: &Arch, os: &Os) -> Res
                 target
             ))
         },
-        Tool::WasmOpt => {
+        Tool::WasmOpt | Tool::Wasm2Js => {
             Ok(format!(
         "https://github.com/WebAssembly/binaryen/releases/download/{vers}/binaryen-{vers}-{target}.tar.gz",
         vers = "version_117", // Make sure to update the version in docs/src/cargo-toml-configuration.md as well
         target = target,
             ))
         }
+        Tool::WasmSnip => bail!("wasm-snip has no prebuilt binaries"),
+        Tool::Wizer => bail!("wizer is only installed with `cargo install`"),
     }
 }
 
@@ -260,6 +275,11 @@ pub fn cargo_install(
         .arg("--root")
         .arg(&tmp);
 
+    // The wizer CLI is behind its optional features
+    if let Tool::Wizer = tool {
+        cmd.arg("--all-features");
+    }
+
     if version != "latest" {
         cmd.arg("--version").arg(version);
     }
@@ -275,6 +295,9 @@ pub fn cargo_install(
         Tool::WasmBindgen => Ok(vec!["wasm-bindgen", "wasm-bindgen-test-runner"]),
         Tool::CargoGenerate => Ok(vec!["cargo-generate"]),
         Tool::WasmOpt => bail!("Cannot install wasm-opt with cargo."),
+        Tool::Wasm2Js => bail!("Cannot install wasm2js with cargo."),
+        Tool::WasmSnip => Ok(vec!["wasm-snip"]),
+        Tool::Wizer => Ok(vec!["wizer"]),
     };
 
     for b in binaries?.iter().cloned() {
@@ -1,9 +1,12 @@
 use anyhow::{bail, Error, Result};
+use serde::Deserialize;
+use std::fmt;
 use std::str::FromStr;
 
 /// The `InstallMode` determines which mode of initialization we are running, and
 /// what install steps we perform.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
 pub enum InstallMode {
     /// Perform all the install steps.
     Normal,
@@ -32,6 +35,24 @@ impl FromStr for InstallMode {
     }
 }
 
+impl TryFrom<String> for InstallMode {
+    type Error = Error;
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for InstallMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            InstallMode::Noinstall => "no-install",
+            InstallMode::Normal => "normal",
+            InstallMode::Force => "force",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 impl InstallMode {
     /// Determines if installation is permitted during a function call based on --mode flag
     pub fn install_permitted(self) -> bool {
@@ -8,6 +8,12 @@ pub enum Tool {
     WasmBindgen,
     /// wasm-opt CLI tool
     WasmOpt,
+    /// wasm2js CLI tool, from the same binaryen release as wasm-opt
+    Wasm2Js,
+    /// wasm-snip CLI tool
+    WasmSnip,
+    /// wizer pre-initialization CLI tool
+    Wizer,
 }
 
 impl fmt::Display for Tool {
@@ -16,6 +22,9 @@ impl fmt::Display for Tool {
             Tool::CargoGenerate => "cargo-generate",
             Tool::WasmBindgen => "wasm-bindgen",
             Tool::WasmOpt => "wasm-opt",
+            Tool::Wasm2Js => "wasm2js",
+            Tool::WasmSnip => "wasm-snip",
+            Tool::Wizer => "wizer",
         };
         write!(f, "{}", s)
     }
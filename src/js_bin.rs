@@ -1,15 +1,152 @@
 //! Writer the converts a stream of WASM bytes into a JS module
 
 use crate::utils::StrUtils;
+use anyhow::{bail, Error, Result};
 use base64::Engine;
+use std::fmt;
 use std::io::{self, Write};
+use std::str::FromStr;
 use std::sync::LazyLock;
 
 const CHUNK_WORDS: usize = 8192;
 
+/// The ECMAScript version that the generated loader is written for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub enum LoaderTarget {
+    /// No `async` functions
+    Es2015,
+    /// No dynamic `import()`
+    Es2017,
+    /// Everything the loader can use
+    #[default]
+    Es2020,
+}
+
+impl FromStr for LoaderTarget {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "es2015" => Ok(LoaderTarget::Es2015),
+            "es2017" => Ok(LoaderTarget::Es2017),
+            "es2020" => Ok(LoaderTarget::Es2020),
+            _ => bail!("Unknown loader target: {}", s),
+        }
+    }
+}
+
+impl TryFrom<String> for LoaderTarget {
+    type Error = Error;
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for LoaderTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            LoaderTarget::Es2015 => "es2015",
+            LoaderTarget::Es2017 => "es2017",
+            LoaderTarget::Es2020 => "es2020",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Options that control the shape of a generated module
+#[derive(Clone, Debug, Default)]
+pub struct LoaderOptions {
+    /// Export `snapshotWasm()` and `restoreWasm()` from the generated module
+    pub snapshot: bool,
+    /// Identifies the embedded wasm, so snapshots are only restored into the
+    /// build that produced them
+    pub build_id: String,
+    /// Module to import instead when `WebAssembly` is not available. Requires
+    /// [LoaderTarget::Es2020].
+    pub wasm2js_module: Option<String>,
+    /// Module with the chunks of a baseline variant, written by
+    /// [WasmJsWriter::chunks_only], to import instead when the engine doesn't
+    /// validate all of the `feature_probes`. Requires [LoaderTarget::Es2020].
+    pub baseline_module: Option<String>,
+    /// Modules from [feature_probe] for the features that the embedded wasm
+    /// uses, but the baseline variant doesn't.
+    pub feature_probes: Vec<&'static [u8]>,
+}
+
+/// Get a tiny module that `WebAssembly.validate` only accepts when the engine
+/// supports the wasm target `feature`, as named by `-C target-feature`.
+pub fn feature_probe(feature: &str) -> Option<&'static [u8]> {
+    // Unless noted otherwise, each probe has a `() -> ()` function whose body
+    // uses the feature
+    let probe: &'static [u8] = match feature {
+        // v128.const 0; drop
+        "simd128" => &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x0a, 0x17, 0x01, 0x15, 0x00, 0xfd, 0x0c, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1a,
+            0x0b,
+        ],
+        // v128.const 0; v128.const 0; i8x16.relaxed_swizzle; drop
+        "relaxed-simd" => &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x0a, 0x2c, 0x01, 0x2a, 0x00, 0xfd, 0x0c, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfd,
+            0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0xfd, 0x80, 0x02, 0x1a, 0x0b,
+        ],
+        // memory.fill on an empty memory
+        "bulk-memory" => &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x05, 0x03, 0x01, 0x00, 0x00, 0x0a, 0x0d, 0x01, 0x0b, 0x00,
+            0x41, 0x00, 0x41, 0x00, 0x41, 0x00, 0xfc, 0x0b, 0x00, 0x0b,
+        ],
+        // i32.const 0; i32.extend8_s; drop
+        "sign-ext" => &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x0a, 0x08, 0x01, 0x06, 0x00, 0x41, 0x00, 0xc0, 0x1a, 0x0b,
+        ],
+        // f32.const 0; i32.trunc_sat_f32_s; drop
+        "nontrapping-fptoint" => &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x0a, 0x0c, 0x01, 0x0a, 0x00, 0x43, 0x00, 0x00, 0x00, 0x00,
+            0xfc, 0x00, 0x1a, 0x0b,
+        ],
+        // A `() -> (i32, i32)` function
+        "multivalue" => &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x06, 0x01, 0x60, 0x00, 0x02,
+            0x7f, 0x7f, 0x03, 0x02, 0x01, 0x00, 0x0a, 0x08, 0x01, 0x06, 0x00, 0x41, 0x00, 0x41,
+            0x00, 0x0b,
+        ],
+        // ref.null extern; drop
+        "reference-types" => &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x0a, 0x07, 0x01, 0x05, 0x00, 0xd0, 0x6f, 0x1a, 0x0b,
+        ],
+        // return_call 0
+        "tail-call" => &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x0a, 0x06, 0x01, 0x04, 0x00, 0x12, 0x00, 0x0b,
+        ],
+        // A global initialized with `i32.const 0; i32.const 0; i32.add`
+        "extended-const" => &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x06, 0x09, 0x01, 0x7f, 0x00, 0x41,
+            0x00, 0x41, 0x00, 0x6a, 0x0b,
+        ],
+        _ => return None,
+    };
+    Some(probe)
+}
+
+/// Name of the runtime module shared by all the generated modules in an
+/// output directory
+pub const RUNTIME_FILENAME: &str = "wasm-js-runtime.js";
+
 pub struct WasmJsWriter<W: Write> {
     out: W,
     imports_module: String,
+    options: LoaderOptions,
+    /// Write the loader after the chunks, rather than only exporting them
+    loader: bool,
     wasm_buf: [u8; CHUNK_WORDS * 3],
     out_buf: [u8; CHUNK_WORDS * 4],
     n: usize,
@@ -19,7 +156,13 @@ pub struct WasmJsWriter<W: Write> {
 
 static PROLOG: LazyLock<Vec<u8>> = LazyLock::new(|| {
     r#"
-const CHUNK_STACK = [
+const CHUNKS = [
+""#
+    .to_os_bytes()
+});
+
+static CHUNKS_ONLY_PROLOG: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    r#"export const CHUNKS = [
 ""#
     .to_os_bytes()
 });
@@ -32,25 +175,117 @@ static CHUNK_SEP: LazyLock<Vec<u8>> = LazyLock::new(|| {
 
 static EPILOG: LazyLock<Vec<u8>> = LazyLock::new(|| {
     r#""
-].reverse();
+];
+"#
+    .to_os_bytes()
+});
+
+static EPILOG_EXPORTS: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    r#"export const WASM_PROMISE = WASM.promise;
+
+export function getWasm() {
+  return WASM_PROMISE;
+}
+"#
+    .to_os_bytes()
+});
+
+static SNAPSHOT_EXPORTS: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    r#"
+export function snapshotWasm() {
+  return runtime.snapshotWasm(WASM, BUILD_ID);
+}
+
+export function restoreWasm(bytes) {
+  return runtime.restoreWasm(WASM, BUILD_ID, bytes);
+}
+"#
+    .to_os_bytes()
+});
+
+/// Instantiation helpers shared by all loader targets
+static RUNTIME_COMMON: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    r#"
+function importsFor(wasm) {
+  return {
+    [wasm.importsKey]: wasm.importObject
+  };
+}
 
+function useInstance(wasm, module, instance) {
+  wasm.module = module;
+  wasm.instance = instance;
+  wasm.importObject.__wbg_set_wasm(instance.exports);
+  // Pre-initialized modules have already run their start function, but the
+  // externref table only gets its reserved slots at runtime
+  if (instance.exports.__wbindgen_start) {
+    instance.exports.__wbindgen_start();
+  } else if (wasm.importObject.__wbindgen_init_externref_table) {
+    wasm.importObject.__wbindgen_init_externref_table();
+  }
+  return wasm.importObject;
+}
+
+// The wasm2js fallback is a module with the same exports as the instance
+function useFallback(wasm, exports) {
+  wasm.importObject.__wbg_set_wasm(exports);
+  if (exports.__wbindgen_start) {
+    exports.__wbindgen_start();
+  }
+  return wasm.importObject;
+}
+
+// Node can inflate and compile synchronously, which is much faster than going
+// through web streams
+function nodeZlib() {
+  if (typeof process === 'undefined' || !process.versions || !process.versions.node) {
+    return null;
+  }
+  if (typeof process.getBuiltinModule !== 'function') {
+    return null;
+  }
+  return process.getBuiltinModule('zlib');
+}
+
+function instantiateNode(wasm, chunks, zlib) {
+  // Only the last chunk can have base64 padding, so they can be decoded together
+  const compressed = Buffer.from(chunks.splice(0).join(''), 'base64');
+  const module = new WebAssembly.Module(zlib.inflateSync(compressed));
+  const instance = new WebAssembly.Instance(module, importsFor(wasm));
+  return useInstance(wasm, module, instance);
+}
+"#
+    .to_os_bytes()
+});
+
+static RUNTIME_INSTANTIATE: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    r#"
 async function chunkBytes(base64) {
   if (typeof Buffer !== 'undefined') {
-    return Buffer.from(base64, 'base64');
+    // Copy out of Buffer's shared pool, since the byte stream takes ownership
+    // of the underlying ArrayBuffer
+    return new Uint8Array(Buffer.from(base64, 'base64'));
   }
   const res = await fetch("data:application/octet-stream;base64," + base64);
-  return res.bytes();
+  return new Uint8Array(await res.arrayBuffer());
 }
 
-export const WASM_PROMISE = (async () => {
+async function instantiate(wasm, chunks, wasm2js) {
+  if (wasm2js && typeof WebAssembly === 'undefined') {
+    return useFallback(wasm, await wasm2js());
+  }
+  const zlib = nodeZlib();
+  if (zlib) {
+    return instantiateNode(wasm, chunks, zlib);
+  }
   const compressed = new ReadableStream({
     type: 'bytes',
     cancel: () => {
-      CHUNK_STACK.length = 0;
+      chunks.length = 0;
     },
     pull: async (ctrl) => {
-      if (CHUNK_STACK.length) {
-        ctrl.enqueue(await chunkBytes(CHUNK_STACK.pop()));
+      if (chunks.length) {
+        ctrl.enqueue(await chunkBytes(chunks.shift()));
       } else {
         ctrl.close();
       }
@@ -65,26 +300,460 @@ export const WASM_PROMISE = (async () => {
       'content-type': 'application/wasm'
     }
   });
-  const {instance} = await WebAssembly.instantiateStreaming(response, {
-    [IMPORTS_KEY]: importObject
+  const {module, instance} = await WebAssembly.instantiateStreaming(response, importsFor(wasm));
+  return useInstance(wasm, module, instance);
+}
+"#
+    .to_os_bytes()
+});
+
+/// Runtime for [LoaderTarget::Es2015], which can't use `async` functions
+static RUNTIME_INSTANTIATE_ES2015: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    r#"
+function chunkBytes(base64) {
+  if (typeof Buffer !== 'undefined') {
+    return Promise.resolve(Buffer.from(base64, 'base64'));
+  }
+  return fetch("data:application/octet-stream;base64," + base64)
+    .then((res) => res.arrayBuffer())
+    .then((buf) => new Uint8Array(buf));
+}
+
+function instantiate(wasm, chunks, wasm2js) {
+  if (wasm2js && typeof WebAssembly === 'undefined') {
+    return wasm2js().then((exports) => useFallback(wasm, exports));
+  }
+  const zlib = nodeZlib();
+  if (zlib) {
+    return Promise.resolve().then(() => instantiateNode(wasm, chunks, zlib));
+  }
+  return Promise.all(chunks.splice(0).map(chunkBytes))
+    .then((bytes) => {
+      const compressed = new Response(new Blob(bytes)).body;
+      const body = compressed.pipeThrough(new DecompressionStream('deflate'));
+      return new Response(body).arrayBuffer();
+    })
+    .then((bytes) => WebAssembly.instantiate(bytes, importsFor(wasm)))
+    .then((result) => useInstance(wasm, result.module, result.instance));
+}
+"#
+    .to_os_bytes()
+});
+
+static RUNTIME_LOAD: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    r#"
+// The embedded chunks may use wasm features that the baseline variant doesn't.
+// Without WebAssembly, there is nothing to probe, and the wasm2js fallback
+// takes over.
+function supportsEmbedded(baseline) {
+  return typeof WebAssembly === 'undefined' ||
+    baseline.probes.every((probe) => WebAssembly.validate(probe));
+}
+
+export function loadWasm(chunks, importsKey, importObject, wasm2js, baseline) {
+  const wasm = {
+    importsKey,
+    importObject,
+    module: null,
+    instance: null,
+    promise: null
+  };
+  if (baseline && !supportsEmbedded(baseline)) {
+    wasm.promise = baseline.load().then((module) => instantiate(wasm, module.CHUNKS, wasm2js));
+  } else {
+    wasm.promise = instantiate(wasm, chunks, wasm2js);
+  }
+  return wasm;
+}
+"#
+    .to_os_bytes()
+});
+
+static RUNTIME_SNAPSHOT: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    r#"
+const SNAPSHOT_MAGIC = 0x534a5357;
+const SNAPSHOT_VERSION = 2;
+
+// Tags of the table entries in a snapshot. Functions aren't stored: the fresh
+// instance gets the same ones from the module's element segments.
+const ENTRY_UNDEFINED = 0;
+const ENTRY_NULL = 1;
+const ENTRY_FALSE = 2;
+const ENTRY_TRUE = 3;
+const ENTRY_NUMBER = 4;
+const ENTRY_STRING = 5;
+const ENTRY_BIGINT = 6;
+const ENTRY_FUNCTION = 7;
+
+function snapshotGlobals(exports) {
+  const globals = [];
+  for (const name of Object.keys(exports)) {
+    const glob = exports[name];
+    if (!(glob instanceof WebAssembly.Global)) {
+      continue;
+    }
+    const value = glob.value;
+    if (typeof value !== 'number' && typeof value !== 'bigint') {
+      continue;
+    }
+    try {
+      // Only mutable globals can change after instantiation
+      glob.value = value;
+    } catch (e) {
+      continue;
+    }
+    globals.push([name, value]);
+  }
+  return globals;
+}
+
+function snapshotMemories(exports) {
+  return Object.keys(exports)
+    .filter((name) => exports[name] instanceof WebAssembly.Memory)
+    .map((name) => [name, new Uint8Array(exports[name].buffer)]);
+}
+
+// The JS values that wasm-bindgen keeps for Rust live in an exported externref
+// table, so their slots have to survive a restore along with the memory that
+// refers to them
+function snapshotTables(exports, enc) {
+  return Object.keys(exports)
+    .filter((name) => exports[name] instanceof WebAssembly.Table)
+    .map((name) => {
+      const table = exports[name];
+      const entries = [];
+      for (let i = 0; i < table.length; ++i) {
+        const value = table.get(i);
+        if (value === undefined) {
+          entries.push([ENTRY_UNDEFINED]);
+        } else if (value === null) {
+          entries.push([ENTRY_NULL]);
+        } else if (value === false) {
+          entries.push([ENTRY_FALSE]);
+        } else if (value === true) {
+          entries.push([ENTRY_TRUE]);
+        } else if (typeof value === 'number') {
+          entries.push([ENTRY_NUMBER, value]);
+        } else if (typeof value === 'string') {
+          entries.push([ENTRY_STRING, enc.encode(value)]);
+        } else if (typeof value === 'bigint') {
+          entries.push([ENTRY_BIGINT, enc.encode(value.toString())]);
+        } else if (typeof value === 'function') {
+          entries.push([ENTRY_FUNCTION]);
+        } else {
+          throw new Error(`Slot ${i} of table ${name} holds a value of type ${typeof value}, which can't be saved in a snapshot`);
+        }
+      }
+      return [enc.encode(name), entries];
+    });
+}
+
+export function snapshotWasm(wasm, buildId) {
+  if (!wasm.instance) {
+    throw new Error('wasm is not instantiated yet. Wait for getWasm() before taking a snapshot.');
+  }
+  const enc = new TextEncoder();
+  const id = enc.encode(buildId);
+  const globals = snapshotGlobals(wasm.instance.exports).map(([name, value]) => [enc.encode(name), value]);
+  const memories = snapshotMemories(wasm.instance.exports).map(([name, bytes]) => [enc.encode(name), bytes]);
+  const tables = snapshotTables(wasm.instance.exports, enc);
+  let size = 24 + id.length;
+  for (const [name] of globals) {
+    size += 13 + name.length;
+  }
+  for (const [name, bytes] of memories) {
+    size += 12 + name.length + bytes.length;
+  }
+  for (const [name, entries] of tables) {
+    size += 8 + name.length;
+    for (const [tag, value] of entries) {
+      if (tag === ENTRY_NUMBER) {
+        size += 9;
+      } else if (tag === ENTRY_STRING || tag === ENTRY_BIGINT) {
+        size += 5 + value.length;
+      } else {
+        size += 1;
+      }
+    }
+  }
+  const out = new Uint8Array(size);
+  const view = new DataView(out.buffer);
+  let pos = 0;
+  const putBytes = (bytes) => {
+    view.setUint32(pos, bytes.length, true);
+    out.set(bytes, pos + 4);
+    pos += 4 + bytes.length;
+  };
+  view.setUint32(0, SNAPSHOT_MAGIC, true);
+  view.setUint32(4, SNAPSHOT_VERSION, true);
+  pos = 8;
+  putBytes(id);
+  view.setUint32(pos, globals.length, true);
+  pos += 4;
+  for (const [name, value] of globals) {
+    putBytes(name);
+    if (typeof value === 'bigint') {
+      out[pos] = 1;
+      view.setBigInt64(pos + 1, value, true);
+    } else {
+      out[pos] = 0;
+      view.setFloat64(pos + 1, value, true);
+    }
+    pos += 9;
+  }
+  view.setUint32(pos, memories.length, true);
+  pos += 4;
+  for (const [name, bytes] of memories) {
+    putBytes(name);
+    view.setFloat64(pos, bytes.length, true);
+    out.set(bytes, pos + 8);
+    pos += 8 + bytes.length;
+  }
+  view.setUint32(pos, tables.length, true);
+  pos += 4;
+  for (const [name, entries] of tables) {
+    putBytes(name);
+    view.setUint32(pos, entries.length, true);
+    pos += 4;
+    for (const [tag, value] of entries) {
+      out[pos] = tag;
+      pos += 1;
+      if (tag === ENTRY_NUMBER) {
+        view.setFloat64(pos, value, true);
+        pos += 8;
+      } else if (tag === ENTRY_STRING || tag === ENTRY_BIGINT) {
+        putBytes(value);
+      }
+    }
+  }
+  return out;
+}
+
+function parseSnapshot(bytes, buildId) {
+  const data = bytes instanceof Uint8Array ? bytes : new Uint8Array(bytes);
+  const view = new DataView(data.buffer, data.byteOffset, data.byteLength);
+  const dec = new TextDecoder();
+  let pos = 0;
+  const getBytes = () => {
+    const len = view.getUint32(pos, true);
+    const ret = data.subarray(pos + 4, pos + 4 + len);
+    pos += 4 + len;
+    return ret;
+  };
+  if (data.length < 12 || view.getUint32(0, true) !== SNAPSHOT_MAGIC) {
+    throw new Error('Not a wasm-js snapshot');
+  }
+  if (view.getUint32(4, true) !== SNAPSHOT_VERSION) {
+    throw new Error('Unsupported wasm-js snapshot version');
+  }
+  pos = 8;
+  const id = dec.decode(getBytes());
+  if (id !== buildId) {
+    throw new Error(`Snapshot was taken from build ${id}, but this is build ${buildId}`);
+  }
+  const globals = [];
+  const globalCount = view.getUint32(pos, true);
+  pos += 4;
+  for (let i = 0; i < globalCount; ++i) {
+    const name = dec.decode(getBytes());
+    globals.push([name, data[pos] === 1 ? view.getBigInt64(pos + 1, true) : view.getFloat64(pos + 1, true)]);
+    pos += 9;
+  }
+  const memories = [];
+  const memoryCount = view.getUint32(pos, true);
+  pos += 4;
+  for (let i = 0; i < memoryCount; ++i) {
+    const name = dec.decode(getBytes());
+    const len = view.getFloat64(pos, true);
+    memories.push([name, data.subarray(pos + 8, pos + 8 + len)]);
+    pos += 8 + len;
+  }
+  const tables = [];
+  const tableCount = view.getUint32(pos, true);
+  pos += 4;
+  for (let i = 0; i < tableCount; ++i) {
+    const name = dec.decode(getBytes());
+    const entryCount = view.getUint32(pos, true);
+    pos += 4;
+    const entries = [];
+    for (let j = 0; j < entryCount; ++j) {
+      const tag = data[pos];
+      pos += 1;
+      if (tag === ENTRY_NUMBER) {
+        entries.push([tag, view.getFloat64(pos, true)]);
+        pos += 8;
+      } else if (tag === ENTRY_STRING) {
+        entries.push([tag, dec.decode(getBytes())]);
+      } else if (tag === ENTRY_BIGINT) {
+        entries.push([tag, BigInt(dec.decode(getBytes()))]);
+      } else {
+        entries.push([tag]);
+      }
+    }
+    tables.push([name, entries]);
+  }
+  return {globals, memories, tables};
+}
+
+function restoreTable(name, table, entries) {
+  if (entries.length > table.length) {
+    table.grow(entries.length - table.length);
+  }
+  entries.forEach(([tag, value], i) => {
+    switch (tag) {
+      case ENTRY_UNDEFINED:
+        table.set(i, undefined);
+        break;
+      case ENTRY_NULL:
+        table.set(i, null);
+        break;
+      case ENTRY_FALSE:
+        table.set(i, false);
+        break;
+      case ENTRY_TRUE:
+        table.set(i, true);
+        break;
+      case ENTRY_FUNCTION:
+        if (typeof table.get(i) !== 'function') {
+          throw new Error(`Snapshot has a function in slot ${i} of table ${name}, but this instance doesn't`);
+        }
+        break;
+      default:
+        table.set(i, value);
+    }
   });
-  importObject.__wbg_set_wasm(instance.exports);
-  instance.exports.__wbindgen_start();
-  return importObject;
-})();
+}
 
-export function getWasm() {
-  return WASM_PROMISE;
+function restoreInstance(wasm, instance, snapshot) {
+  for (const [name, value] of snapshot.globals) {
+    const glob = instance.exports[name];
+    if (!(glob instanceof WebAssembly.Global)) {
+      throw new Error(`Snapshot global ${name} is not exported by this build`);
+    }
+    glob.value = value;
+  }
+  for (const [name, bytes] of snapshot.memories) {
+    const mem = instance.exports[name];
+    if (!(mem instanceof WebAssembly.Memory)) {
+      throw new Error(`Snapshot memory ${name} is not exported by this build`);
+    }
+    const grow = Math.ceil((bytes.length - mem.buffer.byteLength) / 65536);
+    if (grow > 0) {
+      mem.grow(grow);
+    }
+    new Uint8Array(mem.buffer).set(bytes);
+  }
+  const previous = wasm.instance;
+  wasm.instance = instance;
+  wasm.importObject.__wbg_set_wasm(instance.exports);
+  // The start function isn't run again, since it would reset the restored
+  // state, but the externref table needs its reserved slots before the saved
+  // ones are put back
+  if (wasm.importObject.__wbindgen_init_externref_table) {
+    wasm.importObject.__wbindgen_init_externref_table();
+  }
+  for (const [name, entries] of snapshot.tables) {
+    const table = instance.exports[name];
+    if (!(table instanceof WebAssembly.Table)) {
+      throw new Error(`Snapshot table ${name} is not exported by this build`);
+    }
+    restoreTable(name, table, entries);
+  }
+  // Growing by 0 detaches the old buffers, so the bindings' cached memory
+  // views are refreshed on next use.
+  for (const [name] of snapshotMemories(previous.exports)) {
+    previous.exports[name].grow(0);
+  }
+  return wasm.importObject;
+}
+
+export function restoreWasm(wasm, buildId, bytes) {
+  return wasm.promise.then(() => {
+    const snapshot = parseSnapshot(bytes, buildId);
+    return WebAssembly.instantiate(wasm.module, importsFor(wasm))
+      .then((instance) => restoreInstance(wasm, instance, snapshot));
+  });
 }
 "#
     .to_os_bytes()
 });
 
+/// Options that control the shape of the runtime written by [write_runtime]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RuntimeOptions {
+    /// The oldest loader target of the modules that use the runtime
+    pub target: LoaderTarget,
+    /// Whether any of the modules that use the runtime exports
+    /// `snapshotWasm()` and `restoreWasm()`
+    pub snapshot: bool,
+}
+
+impl RuntimeOptions {
+    /// Options that work for the modules using either `self` or `other`.
+    pub fn merge(self, other: RuntimeOptions) -> RuntimeOptions {
+        RuntimeOptions {
+            target: self.target.min(other.target),
+            snapshot: self.snapshot || other.snapshot,
+        }
+    }
+}
+
+/// The comment that starts a runtime, and records the options it was written
+/// with.
+const RUNTIME_HEADER: &str = "/* wasm-js runtime for ";
+
+const RUNTIME_HEADER_SNAPSHOT: &str = " with snapshots";
+
+/// Write the runtime module that the modules generated by [WasmJsWriter]
+/// import as [RUNTIME_FILENAME]. The runtime must be written for the oldest
+/// target of those modules.
+pub fn write_runtime<W: Write>(mut out: W, options: RuntimeOptions) -> io::Result<()> {
+    let prolog = format!(
+        "{}{}{}, shared by the generated modules in this directory */\n",
+        RUNTIME_HEADER,
+        options.target,
+        if options.snapshot {
+            RUNTIME_HEADER_SNAPSHOT
+        } else {
+            ""
+        }
+    );
+    out.write_all(prolog.to_os_bytes().as_ref())?;
+    out.write_all(RUNTIME_COMMON.as_ref())?;
+    if options.target < LoaderTarget::Es2017 {
+        out.write_all(RUNTIME_INSTANTIATE_ES2015.as_ref())?;
+    } else {
+        out.write_all(RUNTIME_INSTANTIATE.as_ref())?;
+    }
+    out.write_all(RUNTIME_LOAD.as_ref())?;
+    if options.snapshot {
+        out.write_all(RUNTIME_SNAPSHOT.as_ref())?;
+    }
+    out.flush()
+}
+
+/// The options that the runtime module `runtime` was written with, or `None`
+/// if it wasn't written by [write_runtime].
+pub fn runtime_options(runtime: &str) -> Option<RuntimeOptions> {
+    let rest = runtime.strip_prefix(RUNTIME_HEADER)?;
+    let header = &rest[..rest.find(',')?];
+    let (target, snapshot) = match header.strip_suffix(RUNTIME_HEADER_SNAPSHOT) {
+        Some(target) => (target, true),
+        None => (header, false),
+    };
+    Some(RuntimeOptions {
+        target: target.parse().ok()?,
+        snapshot,
+    })
+}
+
 impl<W: Write> WasmJsWriter<W> {
-    pub fn new(out: W, imports_module: &str) -> Self {
+    pub fn new(out: W, imports_module: &str, options: LoaderOptions) -> Self {
         Self {
             out,
             imports_module: imports_module.to_string(),
+            options,
+            loader: true,
             wasm_buf: [0; CHUNK_WORDS * 3],
             out_buf: [0; CHUNK_WORDS * 4],
             n: 0,
@@ -93,6 +762,24 @@ impl<W: Write> WasmJsWriter<W> {
         }
     }
 
+    /// Create a writer for a module that only exports the chunks as `CHUNKS`,
+    /// for the baseline variant that a loader imports lazily.
+    pub fn chunks_only(out: W) -> Self {
+        Self {
+            loader: false,
+            ..Self::new(out, "", LoaderOptions::default())
+        }
+    }
+
+    fn write_snapshot_exports(&mut self) -> std::io::Result<()> {
+        if self.options.snapshot {
+            let build_id = format!("\nconst BUILD_ID = '{}';\n", self.options.build_id);
+            self.out.write_all(build_id.to_os_bytes().as_ref())?;
+            self.out.write_all(SNAPSHOT_EXPORTS.as_ref())?;
+        }
+        Ok(())
+    }
+
     fn push_chunk(&mut self) -> std::io::Result<()> {
         if self.finished {
             return Err(io::Error::new(
@@ -106,12 +793,16 @@ impl<W: Write> WasmJsWriter<W> {
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
 
         if !self.started {
-            let opening = format!(
-                "import * as importObject from '{}';\nconst IMPORTS_KEY = '{}'\n;",
-                self.imports_module, self.imports_module
-            );
-            self.out.write_all(opening.to_os_bytes().as_ref())?;
-            self.out.write_all(PROLOG.as_ref())?;
+            if self.loader {
+                let opening = format!(
+                    "import * as importObject from '{}';\nimport * as runtime from './{}';\n",
+                    self.imports_module, RUNTIME_FILENAME
+                );
+                self.out.write_all(opening.to_os_bytes().as_ref())?;
+                self.out.write_all(PROLOG.as_ref())?;
+            } else {
+                self.out.write_all(CHUNKS_ONLY_PROLOG.as_ref())?;
+            }
             self.started = true;
         } else {
             self.out.write_all(CHUNK_SEP.as_ref())?;
@@ -151,6 +842,46 @@ impl<W: Write> Write for WasmJsWriter<W> {
         self.push_chunk()?;
         self.finished = true;
         self.out.write_all(EPILOG.as_ref())?;
+        if !self.loader {
+            return self.out.flush();
+        }
+        // The wasm2js fallback and the baseline variant are thunks, so that
+        // they are only fetched when they are needed
+        let wasm2js = match &self.options.wasm2js_module {
+            Some(module) => format!(", () => import('{}')", module),
+            None => String::new(),
+        };
+        let baseline = match &self.options.baseline_module {
+            Some(module) => {
+                let probes: Vec<String> = self
+                    .options
+                    .feature_probes
+                    .iter()
+                    .map(|probe| {
+                        let bytes: Vec<String> = probe.iter().map(|b| b.to_string()).collect();
+                        format!("new Uint8Array([{}])", bytes.join(", "))
+                    })
+                    .collect();
+                format!(
+                    "{}, {{\n  probes: [\n    {}\n  ],\n  load: () => import('{}')\n}}",
+                    if wasm2js.is_empty() {
+                        ", undefined"
+                    } else {
+                        ""
+                    },
+                    probes.join(",\n    "),
+                    module
+                )
+            }
+            None => String::new(),
+        };
+        let load = format!(
+            "\nconst WASM = runtime.loadWasm(CHUNKS, '{}', importObject{}{});\n",
+            self.imports_module, wasm2js, baseline
+        );
+        self.out.write_all(load.to_os_bytes().as_ref())?;
+        self.out.write_all(EPILOG_EXPORTS.as_ref())?;
+        self.write_snapshot_exports()?;
         self.out.flush()?;
         Ok(())
     }
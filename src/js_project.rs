@@ -0,0 +1,185 @@
+//! Reading settings from the Javascript project around a crate.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// An output directory found in the Javascript project around a crate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InferredOutDir {
+    /// The output directory.
+    pub path: PathBuf,
+    /// The key that holds the output directory.
+    pub key: &'static str,
+    /// The file that holds the key.
+    pub file: PathBuf,
+}
+
+impl fmt::Display for InferredOutDir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}` in {}", self.key, self.file.display())
+    }
+}
+
+/// Find the output directory of the Javascript project in `dir`: the
+/// `wasm-js.outDir` field of `package.json`, or else, when `tsconfig` is set,
+/// the `compilerOptions.outDir` of `tsconfig.json`, following its `extends`.
+pub fn infer_out_dir(dir: &Path, tsconfig: bool) -> Result<Option<InferredOutDir>> {
+    let package_json = dir.join("package.json");
+    if package_json.is_file() {
+        let package = read_json(&package_json)?;
+        if let Some(out_dir) = package.get("wasm-js").and_then(|w| w.get("outDir")) {
+            let out_dir = out_dir.as_str().ok_or_else(|| {
+                anyhow!(
+                    "`wasm-js.outDir` must be a string in {}",
+                    package_json.display()
+                )
+            })?;
+            return Ok(Some(InferredOutDir {
+                path: dir.join(out_dir),
+                key: "wasm-js.outDir",
+                file: package_json,
+            }));
+        }
+    }
+
+    let tsconfig_path = dir.join("tsconfig.json");
+    if tsconfig && tsconfig_path.is_file() {
+        return tsconfig_out_dir(&tsconfig_path, &mut Vec::new());
+    }
+    Ok(None)
+}
+
+/// Get the `compilerOptions.outDir` of the tsconfig file at `path`, or else of
+/// the files it `extends`, where the last one takes precedence.
+fn tsconfig_out_dir(path: &Path, visited: &mut Vec<PathBuf>) -> Result<Option<InferredOutDir>> {
+    let path =
+        fs::canonicalize(path).with_context(|| anyhow!("failed to read: {}", path.display()))?;
+    if visited.contains(&path) {
+        bail!("{} extends itself", path.display());
+    }
+    visited.push(path.clone());
+
+    let config = read_json(&path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    if let Some(out_dir) = config
+        .get("compilerOptions")
+        .and_then(|options| options.get("outDir"))
+        .and_then(|out_dir| out_dir.as_str())
+    {
+        return Ok(Some(InferredOutDir {
+            path: dir.join(out_dir),
+            key: "compilerOptions.outDir",
+            file: path.clone(),
+        }));
+    }
+
+    let extends: Vec<&str> = match config.get("extends") {
+        Some(Value::String(extends)) => vec![extends],
+        Some(Value::Array(extends)) => extends.iter().filter_map(|e| e.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    for extends in extends.into_iter().rev() {
+        let base = resolve_extends(dir, extends)
+            .ok_or_else(|| anyhow!("can't find `{}`, which {} extends", extends, path.display()))?;
+        if let Some(out_dir) = tsconfig_out_dir(&base, visited)? {
+            return Ok(Some(out_dir));
+        }
+    }
+    Ok(None)
+}
+
+/// Find the file that an `extends` of a tsconfig file in `dir` refers to,
+/// either by a relative path or inside a package in `node_modules`.
+fn resolve_extends(dir: &Path, extends: &str) -> Option<PathBuf> {
+    let candidates = |base: PathBuf| {
+        [
+            base.clone(),
+            PathBuf::from(format!("{}.json", base.display())),
+            base.join("tsconfig.json"),
+        ]
+    };
+    if extends.starts_with('.') || Path::new(extends).is_absolute() {
+        return candidates(dir.join(extends))
+            .into_iter()
+            .find(|path| path.is_file());
+    }
+    dir.ancestors()
+        .flat_map(|dir| candidates(dir.join("node_modules").join(extends)))
+        .find(|path| path.is_file())
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let contents =
+        fs::read_to_string(path).with_context(|| anyhow!("failed to read: {}", path.display()))?;
+    serde_json::from_str(&strip_jsonc(&contents))
+        .with_context(|| anyhow!("failed to parse: {}", path.display()))
+}
+
+/// Remove the comments and trailing commas that tsconfig files may have, but
+/// JSON doesn't allow.
+fn strip_jsonc(contents: &str) -> String {
+    let mut stripped = String::with_capacity(contents.len());
+    let mut chars = contents.chars().peekable();
+    let mut pending_comma = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                if std::mem::take(&mut pending_comma) {
+                    stripped.push(',');
+                }
+                stripped.push(c);
+                while let Some(c) = chars.next() {
+                    stripped.push(c);
+                    match c {
+                        '\\' => stripped.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        stripped.push(c);
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                stripped.push(' ');
+            }
+            // Hold on to commas until it's clear that they don't end a list
+            ',' => {
+                if std::mem::replace(&mut pending_comma, true) {
+                    stripped.push(',');
+                }
+            }
+            '}' | ']' => {
+                pending_comma = false;
+                stripped.push(c);
+            }
+            c if c.is_whitespace() => stripped.push(c),
+            c => {
+                if std::mem::take(&mut pending_comma) {
+                    stripped.push(',');
+                }
+                stripped.push(c);
+            }
+        }
+    }
+    if pending_comma {
+        stripped.push(',');
+    }
+    stripped
+}
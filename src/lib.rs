@@ -20,16 +20,22 @@ pub mod bindgen;
 pub mod build;
 pub mod child;
 pub mod command;
+pub mod custom_sections;
 pub mod install;
 pub mod js_bin;
+pub mod js_project;
 pub mod lockfile;
 pub mod manifest;
+pub mod post_process;
+pub mod pre_initialize;
 pub mod progressbar;
 pub mod stamps;
 pub mod target;
 pub mod test;
 pub mod utils;
+pub mod wasm2js;
 pub mod wasm_opt;
+pub mod wasm_snip;
 
 use crate::progressbar::{LogLevel, ProgressOutput};
 use clap::builder::ArgAction;
@@ -2,26 +2,52 @@
 use anyhow::{anyhow, bail, Context, Result};
 
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::command::build::BuildProfile;
+use crate::install::InstallMode;
+use crate::js_bin::LoaderTarget;
 use crate::PBAR;
-use cargo_metadata::Metadata;
-use serde::{self, Deserialize};
-use std::collections::BTreeSet;
+use cargo_metadata::semver::Version;
+use cargo_metadata::{Metadata, MetadataCommand};
+use serde::{self, Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use strsim::levenshtein;
 use toml;
 
 const CARGO_METADATA_KEY: &str = "package.metadata.wasm-js";
+const CARGO_WORKSPACE_METADATA_KEY: &str = "workspace.metadata.wasm-js";
 
 /// Store for metadata learned about a crate
 pub struct CrateData {
     data: Metadata,
     current_idx: usize,
     manifest: CargoManifest,
+    workspace: WorkspaceConfig,
     out_name: Option<String>,
 }
 
+/// Configuration from the root manifest of a workspace, which applies to all
+/// of its members.
+#[derive(Clone, Default)]
+struct WorkspaceConfig {
+    wasm_js: CargoWasmPack,
+    cargo_profile_inherits: BTreeMap<String, String>,
+}
+
+#[derive(Default, Deserialize)]
+struct CargoWorkspaceManifest {
+    #[serde(default)]
+    workspace: CargoWorkspace,
+}
+
+#[derive(Default, Deserialize)]
+struct CargoWorkspace {
+    #[serde(default)]
+    metadata: CargoMetadata,
+}
+
 #[doc(hidden)]
 #[derive(Deserialize)]
 pub struct CargoManifest {
@@ -42,61 +68,151 @@ struct CargoMetadata {
     wasm_js: CargoWasmPack,
 }
 
-#[derive(Default, Deserialize)]
+#[derive(Clone, Default, Deserialize)]
 struct CargoWasmPack {
+    #[serde(default, rename = "out-dir")]
+    out_dir: Option<PathBuf>,
+    #[serde(default, rename = "out-name")]
+    out_name: Option<String>,
+    #[serde(default)]
+    mode: Option<InstallMode>,
+    #[serde(default)]
+    target: Option<String>,
+    #[serde(default)]
+    cdylib: Option<bool>,
+    #[serde(default, rename = "weak-refs")]
+    weak_refs: Option<bool>,
+    #[serde(default, rename = "reference-types")]
+    reference_types: Option<bool>,
     #[serde(default)]
-    profile: CargoWasmPackProfiles,
+    profile: BTreeMap<String, CargoWasmPackProfile>,
+    #[serde(default)]
+    output: Vec<CargoWasmPackOutput>,
 }
 
-#[derive(Deserialize)]
-struct CargoWasmPackProfiles {
-    #[serde(
-        default = "CargoWasmPackProfile::default_dev",
-        deserialize_with = "CargoWasmPackProfile::deserialize_dev"
-    )]
-    dev: CargoWasmPackProfile,
-
-    #[serde(
-        default = "CargoWasmPackProfile::default_release",
-        deserialize_with = "CargoWasmPackProfile::deserialize_release"
-    )]
-    release: CargoWasmPackProfile,
-
-    #[serde(
-        default = "CargoWasmPackProfile::default_profiling",
-        deserialize_with = "CargoWasmPackProfile::deserialize_profiling"
-    )]
-    profiling: CargoWasmPackProfile,
-
-    #[serde(
-        default = "CargoWasmPackProfile::default_custom",
-        deserialize_with = "CargoWasmPackProfile::deserialize_custom"
-    )]
-    custom: CargoWasmPackProfile,
+/// A flavor of the package's output, from `[[package.metadata.wasm-js.output]]`.
+/// It is written with its own file names, and its settings take priority over
+/// the ones of the profile.
+#[derive(Clone)]
+pub struct CargoWasmPackOutput {
+    out_name: String,
+    profile: CargoWasmPackProfile,
+    /// Keys of the entry that aren't settings, which `serde(flatten)` would
+    /// hide from `serde_ignored`.
+    unused_keys: Vec<String>,
 }
 
-impl Default for CargoWasmPackProfiles {
-    fn default() -> CargoWasmPackProfiles {
-        CargoWasmPackProfiles {
-            dev: CargoWasmPackProfile::default_dev(),
-            release: CargoWasmPackProfile::default_release(),
-            profiling: CargoWasmPackProfile::default_profiling(),
-            custom: CargoWasmPackProfile::default_custom(),
-        }
+impl<'de> Deserialize<'de> for CargoWasmPackOutput {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let mut table = toml::value::Table::deserialize(deserializer)?;
+        let out_name = match table.remove("out-name") {
+            Some(toml::Value::String(out_name)) => out_name,
+            Some(_) => return Err(D::Error::custom("`out-name` must be a string")),
+            None => return Err(D::Error::missing_field("out-name")),
+        };
+        let mut unused_keys = Vec::new();
+        let profile = serde_ignored::deserialize(toml::Value::Table(table), |path| {
+            unused_keys.push(path.to_string())
+        })
+        .map_err(D::Error::custom)?;
+        Ok(CargoWasmPackOutput {
+            out_name,
+            profile,
+            unused_keys,
+        })
+    }
+}
+
+impl CargoWasmPackOutput {
+    /// Get the prefix for the file names of this output.
+    pub fn out_name(&self) -> &str {
+        &self.out_name
     }
 }
 
 /// This is where configuration goes for wasm-bindgen, wasm-opt, wasm-snip, or
 /// anything else that we run.
-#[derive(Default, Deserialize)]
+#[derive(Clone, Default, Deserialize)]
 pub struct CargoWasmPackProfile {
+    #[serde(default)]
+    inherits: Option<String>,
     #[serde(default, rename = "wasm-bindgen")]
     wasm_bindgen: CargoWasmPackProfileWasmBindgen,
     #[serde(default, rename = "wasm-opt")]
     wasm_opt: Option<CargoWasmPackProfileWasmOpt>,
+    #[serde(default, rename = "wasm-snip")]
+    wasm_snip: CargoWasmPackProfileWasmSnip,
+    #[serde(default)]
+    snapshot: Option<bool>,
+    #[serde(default, rename = "pre-initialize")]
+    pre_initialize: Option<bool>,
+    #[serde(default)]
+    wasm2js: Option<bool>,
+    #[serde(default, rename = "loader-target")]
+    loader_target: Option<LoaderTarget>,
+    #[serde(default)]
+    features: Option<Vec<String>>,
+    #[serde(default)]
+    rustflags: Option<Vec<String>>,
+    #[serde(default, rename = "target-features")]
+    target_features: Option<Vec<String>>,
+    #[serde(default, rename = "enhanced-target-features")]
+    enhanced_target_features: Option<Vec<String>>,
+    #[serde(default, rename = "compression-level")]
+    compression_level: Option<u32>,
+    #[serde(default, rename = "keep-sections")]
+    keep_sections: Option<Vec<String>>,
+    #[serde(default, rename = "strip-sections")]
+    strip_sections: Option<Vec<String>>,
+    #[serde(default, rename = "post-process")]
+    post_process: Option<Vec<Vec<String>>>,
+    #[serde(skip)]
+    sources: BTreeMap<&'static str, SettingSource>,
 }
 
-#[derive(Default, Deserialize)]
+/// Where the value of a resolved profile setting came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SettingSource {
+    /// The built-in default of the profile.
+    Default,
+    /// `[workspace.metadata.wasm-js]` in the workspace root, or the table of
+    /// the named profile in it.
+    Workspace(Option<String>),
+    /// `[package.metadata.wasm-js]` in the package, or the table of the named
+    /// profile in it.
+    Package(Option<String>),
+    /// A command line flag.
+    CommandLine(String),
+    /// A setting of the Javascript project around the package.
+    JsProject(String),
+    /// The `[[package.metadata.wasm-js.output]]` entry with this `out-name`.
+    Output(String),
+}
+
+impl fmt::Display for SettingSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingSource::Default => write!(f, "default"),
+            SettingSource::Workspace(None) => write!(f, "[{}]", CARGO_WORKSPACE_METADATA_KEY),
+            SettingSource::Workspace(Some(profile)) => {
+                write!(f, "[{}.profile.{}]", CARGO_WORKSPACE_METADATA_KEY, profile)
+            }
+            SettingSource::Package(None) => write!(f, "[{}]", CARGO_METADATA_KEY),
+            SettingSource::Package(Some(profile)) => {
+                write!(f, "[{}.profile.{}]", CARGO_METADATA_KEY, profile)
+            }
+            SettingSource::CommandLine(flag) => write!(f, "{}", flag),
+            SettingSource::JsProject(setting) => write!(f, "{}", setting),
+            SettingSource::Output(out_name) => {
+                write!(f, "[[{}.output]] {}", CARGO_METADATA_KEY, out_name)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
 struct CargoWasmPackProfileWasmBindgen {
     #[serde(default, rename = "debug-js-glue")]
     debug_js_glue: Option<bool>,
@@ -114,13 +230,33 @@ struct CargoWasmPackProfileWasmBindgen {
     split_linked_modules: Option<bool>,
 }
 
+#[derive(Clone, Default, Deserialize)]
+struct CargoWasmPackProfileWasmSnip {
+    #[serde(default)]
+    patterns: Option<Vec<String>>,
+
+    #[serde(default, rename = "snip-rust-panicking-code")]
+    snip_rust_panicking_code: Option<bool>,
+
+    #[serde(default, rename = "snip-rust-fmt-code")]
+    snip_rust_fmt_code: Option<bool>,
+}
+
 #[derive(Clone, Deserialize)]
 #[serde(untagged)]
 enum CargoWasmPackProfileWasmOpt {
     Enabled(bool),
+    Auto(WasmOptAuto),
     ExplicitArgs(Vec<String>),
 }
 
+/// The `"auto"` value of `wasm-opt`, which tries several sets of flags.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum WasmOptAuto {
+    Auto,
+}
+
 impl Default for CargoWasmPackProfileWasmOpt {
     fn default() -> Self {
         CargoWasmPackProfileWasmOpt::Enabled(false)
@@ -130,6 +266,7 @@ impl Default for CargoWasmPackProfileWasmOpt {
 impl CargoWasmPackProfile {
     fn default_dev() -> Self {
         CargoWasmPackProfile {
+            inherits: None,
             wasm_bindgen: CargoWasmPackProfileWasmBindgen {
                 debug_js_glue: Some(true),
                 demangle_name_section: Some(true),
@@ -137,12 +274,31 @@ impl CargoWasmPackProfile {
                 omit_default_module_path: Some(false),
                 split_linked_modules: Some(false),
             },
-            wasm_opt: None,
+            wasm_opt: Some(CargoWasmPackProfileWasmOpt::Enabled(false)),
+            wasm_snip: CargoWasmPackProfileWasmSnip {
+                patterns: Some(Vec::new()),
+                snip_rust_panicking_code: Some(false),
+                snip_rust_fmt_code: Some(false),
+            },
+            snapshot: Some(false),
+            pre_initialize: Some(false),
+            wasm2js: Some(false),
+            loader_target: Some(LoaderTarget::default()),
+            features: Some(Vec::new()),
+            rustflags: Some(Vec::new()),
+            target_features: Some(Vec::new()),
+            enhanced_target_features: Some(Vec::new()),
+            compression_level: Some(9),
+            keep_sections: Some(Vec::new()),
+            strip_sections: Some(Vec::new()),
+            post_process: Some(Vec::new()),
+            sources: BTreeMap::new(),
         }
     }
 
     fn default_release() -> Self {
         CargoWasmPackProfile {
+            inherits: None,
             wasm_bindgen: CargoWasmPackProfileWasmBindgen {
                 debug_js_glue: Some(false),
                 demangle_name_section: Some(true),
@@ -151,11 +307,30 @@ impl CargoWasmPackProfile {
                 split_linked_modules: Some(false),
             },
             wasm_opt: Some(CargoWasmPackProfileWasmOpt::Enabled(true)),
+            wasm_snip: CargoWasmPackProfileWasmSnip {
+                patterns: Some(Vec::new()),
+                snip_rust_panicking_code: Some(false),
+                snip_rust_fmt_code: Some(false),
+            },
+            snapshot: Some(false),
+            pre_initialize: Some(false),
+            wasm2js: Some(false),
+            loader_target: Some(LoaderTarget::default()),
+            features: Some(Vec::new()),
+            rustflags: Some(Vec::new()),
+            target_features: Some(Vec::new()),
+            enhanced_target_features: Some(Vec::new()),
+            compression_level: Some(9),
+            keep_sections: Some(Vec::new()),
+            strip_sections: Some(Vec::new()),
+            post_process: Some(Vec::new()),
+            sources: BTreeMap::new(),
         }
     }
 
     fn default_profiling() -> Self {
         CargoWasmPackProfile {
+            inherits: None,
             wasm_bindgen: CargoWasmPackProfileWasmBindgen {
                 debug_js_glue: Some(false),
                 demangle_name_section: Some(true),
@@ -164,11 +339,30 @@ impl CargoWasmPackProfile {
                 split_linked_modules: Some(false),
             },
             wasm_opt: Some(CargoWasmPackProfileWasmOpt::Enabled(true)),
+            wasm_snip: CargoWasmPackProfileWasmSnip {
+                patterns: Some(Vec::new()),
+                snip_rust_panicking_code: Some(false),
+                snip_rust_fmt_code: Some(false),
+            },
+            snapshot: Some(false),
+            pre_initialize: Some(false),
+            wasm2js: Some(false),
+            loader_target: Some(LoaderTarget::default()),
+            features: Some(Vec::new()),
+            rustflags: Some(Vec::new()),
+            target_features: Some(Vec::new()),
+            enhanced_target_features: Some(Vec::new()),
+            compression_level: Some(9),
+            keep_sections: Some(Vec::new()),
+            strip_sections: Some(Vec::new()),
+            post_process: Some(Vec::new()),
+            sources: BTreeMap::new(),
         }
     }
 
     fn default_custom() -> Self {
         CargoWasmPackProfile {
+            inherits: None,
             wasm_bindgen: CargoWasmPackProfileWasmBindgen {
                 debug_js_glue: Some(false),
                 demangle_name_section: Some(true),
@@ -177,45 +371,27 @@ impl CargoWasmPackProfile {
                 split_linked_modules: Some(false),
             },
             wasm_opt: Some(CargoWasmPackProfileWasmOpt::Enabled(true)),
+            wasm_snip: CargoWasmPackProfileWasmSnip {
+                patterns: Some(Vec::new()),
+                snip_rust_panicking_code: Some(false),
+                snip_rust_fmt_code: Some(false),
+            },
+            snapshot: Some(false),
+            pre_initialize: Some(false),
+            wasm2js: Some(false),
+            loader_target: Some(LoaderTarget::default()),
+            features: Some(Vec::new()),
+            rustflags: Some(Vec::new()),
+            target_features: Some(Vec::new()),
+            enhanced_target_features: Some(Vec::new()),
+            compression_level: Some(9),
+            keep_sections: Some(Vec::new()),
+            strip_sections: Some(Vec::new()),
+            post_process: Some(Vec::new()),
+            sources: BTreeMap::new(),
         }
     }
 
-    fn deserialize_dev<'de, D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let mut profile = <Option<Self>>::deserialize(deserializer)?.unwrap_or_default();
-        profile.update_with_defaults(&Self::default_dev());
-        Ok(profile)
-    }
-
-    fn deserialize_release<'de, D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let mut profile = <Option<Self>>::deserialize(deserializer)?.unwrap_or_default();
-        profile.update_with_defaults(&Self::default_release());
-        Ok(profile)
-    }
-
-    fn deserialize_profiling<'de, D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let mut profile = <Option<Self>>::deserialize(deserializer)?.unwrap_or_default();
-        profile.update_with_defaults(&Self::default_profiling());
-        Ok(profile)
-    }
-
-    fn deserialize_custom<'de, D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let mut profile = <Option<Self>>::deserialize(deserializer)?.unwrap_or_default();
-        profile.update_with_defaults(&Self::default_custom());
-        Ok(profile)
-    }
-
     fn update_with_defaults(&mut self, defaults: &Self) {
         macro_rules! d {
             ( $( $path:ident ).* ) => {
@@ -227,10 +403,133 @@ impl CargoWasmPackProfile {
         d!(wasm_bindgen.dwarf_debug_info);
         d!(wasm_bindgen.omit_default_module_path);
         d!(wasm_bindgen.split_linked_modules);
+        d!(wasm_snip.snip_rust_panicking_code);
+        d!(wasm_snip.snip_rust_fmt_code);
+        d!(snapshot);
+        d!(pre_initialize);
+        d!(wasm2js);
+        d!(loader_target);
+        d!(compression_level);
 
         if self.wasm_opt.is_none() {
             self.wasm_opt = defaults.wasm_opt.clone();
         }
+        if self.wasm_snip.patterns.is_none() {
+            self.wasm_snip.patterns = defaults.wasm_snip.patterns.clone();
+        }
+        if self.features.is_none() {
+            self.features = defaults.features.clone();
+        }
+        if self.rustflags.is_none() {
+            self.rustflags = defaults.rustflags.clone();
+        }
+        if self.target_features.is_none() {
+            self.target_features = defaults.target_features.clone();
+        }
+        if self.enhanced_target_features.is_none() {
+            self.enhanced_target_features = defaults.enhanced_target_features.clone();
+        }
+        if self.keep_sections.is_none() {
+            self.keep_sections = defaults.keep_sections.clone();
+        }
+        if self.strip_sections.is_none() {
+            self.strip_sections = defaults.strip_sections.clone();
+        }
+        if self.post_process.is_none() {
+            self.post_process = defaults.post_process.clone();
+        }
+    }
+
+    /// The settings that this profile sets, as `key = value` pairs in TOML
+    /// syntax, with `None` for the ones it leaves out.
+    fn entries(&self) -> Vec<(&'static str, Option<String>)> {
+        fn toml<T: Serialize>(value: &Option<T>) -> Option<String> {
+            value.as_ref().map(|value| {
+                toml::Value::try_from(value)
+                    .map(|value| value.to_string())
+                    .unwrap_or_default()
+            })
+        }
+        let bindgen = &self.wasm_bindgen;
+        vec![
+            ("wasm-bindgen.debug-js-glue", toml(&bindgen.debug_js_glue)),
+            (
+                "wasm-bindgen.demangle-name-section",
+                toml(&bindgen.demangle_name_section),
+            ),
+            (
+                "wasm-bindgen.dwarf-debug-info",
+                toml(&bindgen.dwarf_debug_info),
+            ),
+            (
+                "wasm-bindgen.omit-default-module-path",
+                toml(&bindgen.omit_default_module_path),
+            ),
+            (
+                "wasm-bindgen.split-linked-modules",
+                toml(&bindgen.split_linked_modules),
+            ),
+            (
+                "wasm-opt",
+                self.wasm_opt.as_ref().map(|wasm_opt| match wasm_opt {
+                    CargoWasmPackProfileWasmOpt::Enabled(enabled) => enabled.to_string(),
+                    CargoWasmPackProfileWasmOpt::Auto(_) => "\"auto\"".to_string(),
+                    CargoWasmPackProfileWasmOpt::ExplicitArgs(args) => {
+                        toml(&Some(args)).unwrap_or_default()
+                    }
+                }),
+            ),
+            ("wasm-snip.patterns", toml(&self.wasm_snip.patterns)),
+            (
+                "wasm-snip.snip-rust-panicking-code",
+                toml(&self.wasm_snip.snip_rust_panicking_code),
+            ),
+            (
+                "wasm-snip.snip-rust-fmt-code",
+                toml(&self.wasm_snip.snip_rust_fmt_code),
+            ),
+            ("snapshot", toml(&self.snapshot)),
+            ("pre-initialize", toml(&self.pre_initialize)),
+            ("wasm2js", toml(&self.wasm2js)),
+            (
+                "loader-target",
+                self.loader_target.map(|target| format!("\"{}\"", target)),
+            ),
+            ("features", toml(&self.features)),
+            ("rustflags", toml(&self.rustflags)),
+            ("target-features", toml(&self.target_features)),
+            (
+                "enhanced-target-features",
+                toml(&self.enhanced_target_features),
+            ),
+            ("compression-level", toml(&self.compression_level)),
+            ("keep-sections", toml(&self.keep_sections)),
+            ("strip-sections", toml(&self.strip_sections)),
+            ("post-process", toml(&self.post_process)),
+        ]
+    }
+
+    /// Get the resolved settings of this profile, as `key = value` pairs in
+    /// TOML syntax, along with where each value came from.
+    pub fn settings(&self) -> Vec<(&'static str, String, SettingSource)> {
+        self.entries()
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let source = self
+                    .sources
+                    .get(key)
+                    .cloned()
+                    .unwrap_or(SettingSource::Default);
+                Some((key, value?, source))
+            })
+            .collect()
+    }
+
+    /// Turn `wasm-opt` off, as requested by the command line `flag`.
+    pub fn disable_wasm_opt(&mut self, flag: &str) {
+        self.wasm_opt = Some(CargoWasmPackProfileWasmOpt::Enabled(false));
+        self.sources
+            .insert("wasm-opt", SettingSource::CommandLine(flag.to_string()));
     }
 
     /// Get this profile's configured `[wasm-bindgen.debug-js-glue]` value.
@@ -258,14 +557,150 @@ impl CargoWasmPackProfile {
         self.wasm_bindgen.split_linked_modules.unwrap()
     }
 
+    /// Get this profile's configured `[wasm-snip.patterns]` value.
+    pub fn wasm_snip_patterns(&self) -> &[String] {
+        self.wasm_snip.patterns.as_ref().unwrap()
+    }
+
+    /// Get this profile's configured `[wasm-snip.snip-rust-panicking-code]` value.
+    pub fn wasm_snip_rust_panicking_code(&self) -> bool {
+        self.wasm_snip.snip_rust_panicking_code.unwrap()
+    }
+
+    /// Get this profile's configured `[wasm-snip.snip-rust-fmt-code]` value.
+    pub fn wasm_snip_rust_fmt_code(&self) -> bool {
+        self.wasm_snip.snip_rust_fmt_code.unwrap()
+    }
+
+    /// Does this profile snip any functions with `wasm-snip`?
+    pub fn wasm_snip(&self) -> bool {
+        !self.wasm_snip_patterns().is_empty()
+            || self.wasm_snip_rust_panicking_code()
+            || self.wasm_snip_rust_fmt_code()
+    }
+
+    /// Get this profile's configured `[snapshot]` value.
+    pub fn snapshot(&self) -> bool {
+        self.snapshot.unwrap()
+    }
+
+    /// Get this profile's configured `[pre-initialize]` value.
+    pub fn pre_initialize(&self) -> bool {
+        self.pre_initialize.unwrap()
+    }
+
+    /// Get this profile's configured `[wasm2js]` value.
+    pub fn wasm2js(&self) -> bool {
+        self.wasm2js.unwrap()
+    }
+
+    /// Get this profile's configured `[loader-target]` value.
+    pub fn loader_target(&self) -> LoaderTarget {
+        self.loader_target.unwrap()
+    }
+
+    /// Get this profile's configured `[features]` value.
+    pub fn features(&self) -> &[String] {
+        self.features.as_ref().unwrap()
+    }
+
+    /// Get this profile's configured `[rustflags]` value.
+    pub fn rustflags(&self) -> &[String] {
+        self.rustflags.as_ref().unwrap()
+    }
+
+    /// Get this profile's configured `[target-features]` value.
+    pub fn target_features(&self) -> &[String] {
+        self.target_features.as_ref().unwrap()
+    }
+
+    /// Get this profile's configured `[enhanced-target-features]` value.
+    pub fn enhanced_target_features(&self) -> &[String] {
+        self.enhanced_target_features.as_ref().unwrap()
+    }
+
+    /// Get the profile of the enhanced variant, which is built with the
+    /// `enhanced-target-features` on top of the `target-features`, if there
+    /// are any.
+    pub fn enhanced_profile(&self) -> Option<CargoWasmPackProfile> {
+        if self.enhanced_target_features().is_empty() {
+            return None;
+        }
+        let mut profile = self.clone();
+        let mut target_features = self.target_features().to_vec();
+        target_features.extend(self.enhanced_target_features().iter().cloned());
+        profile.target_features = Some(target_features);
+        profile.enhanced_target_features = Some(Vec::new());
+        Some(profile)
+    }
+
+    /// Get this profile's configured `[compression-level]` value.
+    pub fn compression_level(&self) -> u32 {
+        self.compression_level.unwrap()
+    }
+
+    /// Get this profile's configured `[keep-sections]` value.
+    pub fn keep_sections(&self) -> &[String] {
+        self.keep_sections.as_ref().unwrap()
+    }
+
+    /// Get this profile's configured `[strip-sections]` value.
+    pub fn strip_sections(&self) -> &[String] {
+        self.strip_sections.as_ref().unwrap()
+    }
+
+    /// Get this profile's configured `[post-process]` commands.
+    pub fn post_process(&self) -> &[Vec<String>] {
+        self.post_process.as_ref().unwrap()
+    }
+
+    /// Get the flags to pass to `rustc` for this profile: its `rustflags`,
+    /// followed by a `-C target-feature` flag for its `target-features`.
+    pub fn rustc_flags(&self) -> Vec<String> {
+        let mut flags = self.rustflags().to_vec();
+        if !self.target_features().is_empty() {
+            let features: Vec<String> = self
+                .target_features()
+                .iter()
+                .map(|feature| {
+                    if feature.starts_with(['+', '-']) {
+                        feature.clone()
+                    } else {
+                        format!("+{}", feature)
+                    }
+                })
+                .collect();
+            flags.push(format!("-Ctarget-feature={}", features.join(",")));
+        }
+        flags
+    }
+
     /// Get this profile's configured arguments for `wasm-opt`, if enabled.
+    /// With `wasm-opt = "auto"`, this is `-O`, for the tools that need a
+    /// single set of flags.
     pub fn wasm_opt_args(&self) -> Option<Vec<String>> {
         match self.wasm_opt.as_ref()? {
             CargoWasmPackProfileWasmOpt::Enabled(false) => None,
-            CargoWasmPackProfileWasmOpt::Enabled(true) => Some(vec!["-O".to_string()]),
+            CargoWasmPackProfileWasmOpt::Enabled(true) | CargoWasmPackProfileWasmOpt::Auto(_) => {
+                Some(vec!["-O".to_string()])
+            }
             CargoWasmPackProfileWasmOpt::ExplicitArgs(s) => Some(s.clone()),
         }
     }
+
+    /// Is `wasm-opt = "auto"`, which picks the flags that give the smallest
+    /// compressed wasm?
+    pub fn wasm_opt_auto(&self) -> bool {
+        matches!(self.wasm_opt, Some(CargoWasmPackProfileWasmOpt::Auto(_)))
+    }
+}
+
+/// Whether the key at `path`, which serde ignored, looks like it was meant
+/// for the wasm-js table at `metadata_key`.
+fn is_wasm_js_key(metadata_key: &str, path: &str) -> bool {
+    let levenshtein_threshold = 1;
+    path.starts_with(metadata_key.trim_end_matches(".wasm-js"))
+        && (path.contains("wasm-js") || levenshtein(metadata_key, path) <= levenshtein_threshold)
 }
 
 #[doc(hidden)]
@@ -277,7 +712,11 @@ pub struct ManifestAndUnsedKeys {
 impl CrateData {
     /// Reads all metadata for the crate whose manifest is inside the directory
     /// specified by `path`.
-    pub fn new(crate_path: &Path, out_name: Option<String>) -> Result<CrateData> {
+    pub fn new(
+        crate_path: &Path,
+        out_name: Option<String>,
+        toolchain: Option<&str>,
+    ) -> Result<CrateData> {
         let manifest_path = crate_path.join("Cargo.toml");
         if !manifest_path.is_file() {
             bail!(
@@ -287,11 +726,97 @@ impl CrateData {
             )
         }
 
-        let data = cargo_metadata::MetadataCommand::new()
-            .manifest_path(&manifest_path)
-            .exec()?;
+        let data = CrateData::metadata_command(crate_path, toolchain).exec()?;
 
-        let manifest_and_keys = CrateData::parse_crate_data(&manifest_path)?;
+        let workspace = CrateData::parse_workspace_config(data.workspace_root.as_std_path())?;
+        CrateData::from_metadata(data, &manifest_path, workspace, out_name)
+    }
+
+    /// Reads all metadata for the workspace containing the manifest inside
+    /// `crate_path`, and returns the members selected by name in `packages`.
+    /// When `workspace` is set, every member with a cdylib target or a
+    /// `package.metadata.wasm-js` table is selected as well.
+    pub fn workspace_members(
+        crate_path: &Path,
+        packages: &[String],
+        workspace: bool,
+        toolchain: Option<&str>,
+    ) -> Result<Vec<CrateData>> {
+        let manifest_path = crate_path.join("Cargo.toml");
+        if !manifest_path.is_file() {
+            bail!(
+                "crate directory is missing a `Cargo.toml` file; is `{}` the \
+                 wrong directory?",
+                crate_path.display()
+            )
+        }
+
+        let data = CrateData::metadata_command(crate_path, toolchain).exec()?;
+
+        let members: Vec<&cargo_metadata::Package> = data
+            .packages
+            .iter()
+            .filter(|pkg| data.workspace_members.contains(&pkg.id))
+            .collect();
+        for name in packages {
+            if !members.iter().any(|pkg| &pkg.name == name) {
+                bail!(
+                    "package `{}` is not a member of the workspace at {}",
+                    name,
+                    data.workspace_root
+                )
+            }
+        }
+
+        let selected: Vec<_> = members
+            .into_iter()
+            .filter(|pkg| {
+                packages.contains(&pkg.name)
+                    || (workspace
+                        && (pkg
+                            .targets
+                            .iter()
+                            .any(|t| t.kind.iter().any(|k| k == "cdylib"))
+                            || pkg.metadata.get("wasm-js").is_some()))
+            })
+            .map(|pkg| pkg.manifest_path.clone().into_std_path_buf())
+            .collect();
+        if selected.is_empty() {
+            bail!(
+                "no member of the workspace at {} has a cdylib target or `package.metadata.wasm-js`",
+                data.workspace_root
+            )
+        }
+
+        let workspace = CrateData::parse_workspace_config(data.workspace_root.as_std_path())?;
+        selected
+            .iter()
+            .map(|manifest_path| {
+                CrateData::from_metadata(data.clone(), manifest_path, workspace.clone(), None)
+            })
+            .collect()
+    }
+
+    /// Create a `cargo metadata` command for the crate in `crate_path`, that
+    /// runs against the same toolchain as the build.
+    fn metadata_command(crate_path: &Path, toolchain: Option<&str>) -> MetadataCommand {
+        let mut command = MetadataCommand::new();
+        command
+            .manifest_path(crate_path.join("Cargo.toml"))
+            .current_dir(crate_path);
+        if let Some(toolchain) = toolchain {
+            command.env("RUSTUP_TOOLCHAIN", toolchain);
+        }
+        command
+    }
+
+    fn from_metadata(
+        data: Metadata,
+        manifest_path: &Path,
+        workspace: WorkspaceConfig,
+        out_name: Option<String>,
+    ) -> Result<CrateData> {
+        let manifest_and_keys = CrateData::parse_crate_data(manifest_path)?;
         CrateData::warn_for_unused_keys(&manifest_and_keys);
 
         let manifest = manifest_and_keys.manifest;
@@ -300,18 +825,81 @@ impl CrateData {
             .iter()
             .position(|pkg| {
                 pkg.name == manifest.package.name
-                    && CrateData::is_same_path(pkg.manifest_path.as_std_path(), &manifest_path)
+                    && CrateData::is_same_path(pkg.manifest_path.as_std_path(), manifest_path)
             })
             .ok_or_else(|| anyhow!("failed to find package in metadata"))?;
 
+        let outputs = &manifest.package.metadata.wasm_js.output;
+        for (i, output) in outputs.iter().enumerate() {
+            if output.profile.inherits.is_some() {
+                bail!(
+                    "`inherits` can't be set in `[[{}.output]]`, because outputs build on the selected profile",
+                    CARGO_METADATA_KEY
+                );
+            }
+            if outputs[..i].iter().any(|o| o.out_name == output.out_name) {
+                bail!(
+                    "there is more than one `[[{}.output]]` with the out-name `{}`",
+                    CARGO_METADATA_KEY,
+                    output.out_name
+                );
+            }
+        }
+        let out_name = out_name.or_else(|| manifest.package.metadata.wasm_js.out_name.clone());
         Ok(CrateData {
             data,
             manifest,
             current_idx,
+            workspace,
             out_name,
         })
     }
 
+    /// Read the `workspace.metadata.wasm-js` table of the workspace root, and
+    /// which profile each of the custom cargo profiles in the workspace
+    /// inherits from.
+    fn parse_workspace_config(workspace_root: &Path) -> Result<WorkspaceConfig> {
+        let manifest_path = workspace_root.join("Cargo.toml");
+        let contents = fs::read_to_string(&manifest_path)
+            .with_context(|| anyhow!("failed to read: {}", manifest_path.display()))?;
+
+        let mut unused_keys = BTreeSet::new();
+        let workspace: CargoWorkspaceManifest =
+            serde_ignored::deserialize(toml::Deserializer::new(&contents), |path| {
+                let path_string = path.to_string();
+                if is_wasm_js_key(CARGO_WORKSPACE_METADATA_KEY, &path_string) {
+                    unused_keys.insert(path_string);
+                }
+            })
+            .with_context(|| anyhow!("failed to parse manifest: {}", manifest_path.display()))?;
+        CrateData::warn_for_keys(&unused_keys);
+        let wasm_js = &workspace.workspace.metadata.wasm_js;
+        if wasm_js.out_name.is_some() || !wasm_js.output.is_empty() {
+            bail!(
+                "`out-name` and `output` can't be set in `[{}]`, because all members would write to the same files",
+                CARGO_WORKSPACE_METADATA_KEY
+            );
+        }
+
+        let manifest: toml::Value = toml::from_str(&contents)
+            .with_context(|| anyhow!("failed to parse manifest: {}", manifest_path.display()))?;
+        let mut inherits: BTreeMap<String, String> = [("bench", "release"), ("test", "dev")]
+            .iter()
+            .map(|(name, parent)| (name.to_string(), parent.to_string()))
+            .collect();
+        if let Some(profiles) = manifest.get("profile").and_then(|p| p.as_table()) {
+            for (name, profile) in profiles {
+                if let Some(parent) = profile.get("inherits").and_then(|i| i.as_str()) {
+                    inherits.insert(name.clone(), parent.to_string());
+                }
+            }
+        }
+        Ok(WorkspaceConfig {
+            wasm_js: workspace.workspace.metadata.wasm_js,
+            cargo_profile_inherits: inherits,
+        })
+    }
+
     fn is_same_path(path1: &Path, path2: &Path) -> bool {
         if let Ok(path1) = fs::canonicalize(&path1) {
             if let Ok(path2) = fs::canonicalize(&path2) {
@@ -334,19 +922,20 @@ impl CrateData {
         let manifest = toml::Deserializer::new(&manifest);
 
         let mut unused_keys = BTreeSet::new();
-        let levenshtein_threshold = 1;
 
         let manifest: CargoManifest = serde_ignored::deserialize(manifest, |path| {
             let path_string = path.to_string();
 
-            if path_string.starts_with("package.metadata")
-                && (path_string.contains("wasm-js")
-                    || levenshtein(CARGO_METADATA_KEY, &path_string) <= levenshtein_threshold)
-            {
+            if is_wasm_js_key(CARGO_METADATA_KEY, &path_string) {
                 unused_keys.insert(path_string);
             }
         })
         .with_context(|| anyhow!("failed to parse manifest: {}", manifest_path.display()))?;
+        for (i, output) in manifest.package.metadata.wasm_js.output.iter().enumerate() {
+            for key in &output.unused_keys {
+                unused_keys.insert(format!("{}.output.{}.{}", CARGO_METADATA_KEY, i, key));
+            }
+        }
 
         Ok(ManifestAndUnsedKeys {
             manifest,
@@ -357,7 +946,11 @@ impl CrateData {
     /// Iterating through all the passed `unused_keys` and output
     /// a warning for each unknown key.
     pub fn warn_for_unused_keys(manifest_and_keys: &ManifestAndUnsedKeys) {
-        manifest_and_keys.unused_keys.iter().for_each(|path| {
+        CrateData::warn_for_keys(&manifest_and_keys.unused_keys);
+    }
+
+    fn warn_for_keys(unused_keys: &BTreeSet<String>) {
+        unused_keys.iter().for_each(|path| {
             PBAR.warn(&format!(
                 "\"{}\" is an unknown key and will be ignored. Please check your Cargo.toml.",
                 path
@@ -366,53 +959,351 @@ impl CrateData {
     }
 
     /// Get the configured profile.
-    pub fn configured_profile(&self, profile: BuildProfile) -> &CargoWasmPackProfile {
+    pub fn configured_profile(&self, profile: BuildProfile) -> Result<CargoWasmPackProfile> {
         match profile {
-            BuildProfile::Dev => &self.manifest.package.metadata.wasm_js.profile.dev,
-            BuildProfile::Profiling => &self.manifest.package.metadata.wasm_js.profile.profiling,
-            BuildProfile::Release => &self.manifest.package.metadata.wasm_js.profile.release,
-            BuildProfile::Custom(_) => &self.manifest.package.metadata.wasm_js.profile.custom,
+            BuildProfile::Dev => self.resolve_profile("dev"),
+            BuildProfile::Profiling => self.resolve_profile("profiling"),
+            BuildProfile::Release => self.resolve_profile("release"),
+            BuildProfile::Custom(name) => self.resolve_profile(&name),
+        }
+    }
+
+    /// Resolve the settings of the profile `name`. Settings that its table in
+    /// the package metadata leaves out come from its table in the workspace
+    /// metadata, then from the profile it `inherits`, or else from the
+    /// profile that cargo's own profile of the same name inherits, and so on,
+    /// down to the defaults of `dev`, `release` or `profiling`. Custom
+    /// profiles without a table of their own use the `custom` table, if there
+    /// is one.
+    fn resolve_profile(&self, name: &str) -> Result<CargoWasmPackProfile> {
+        let package = &self.manifest.package.metadata.wasm_js.profile;
+        let workspace = &self.workspace.wasm_js.profile;
+        let tables_of = |name: &str| {
+            let package = package
+                .get(name)
+                .map(|table| (SettingSource::Package(Some(name.to_string())), table));
+            let workspace = workspace
+                .get(name)
+                .map(|table| (SettingSource::Workspace(Some(name.to_string())), table));
+            package.into_iter().chain(workspace).collect::<Vec<_>>()
+        };
+
+        let mut names: Vec<String> = Vec::new();
+        let mut layers: Vec<(SettingSource, &CargoWasmPackProfile)> = Vec::new();
+        let mut current = name.to_string();
+        let base = loop {
+            if names.contains(&current) {
+                names.push(current);
+                bail!("profile inheritance has a cycle: {}", names.join(" -> "));
+            }
+            let base = match current.as_str() {
+                "dev" => Some(CargoWasmPackProfile::default_dev()),
+                "release" => Some(CargoWasmPackProfile::default_release()),
+                "profiling" => Some(CargoWasmPackProfile::default_profiling()),
+                _ => None,
+            };
+            let mut tables = tables_of(&current);
+            if tables.is_empty() && names.is_empty() && base.is_none() {
+                tables = tables_of("custom");
+            }
+            let inherits = tables.iter().find_map(|(_, table)| table.inherits.clone());
+            let defined = !tables.is_empty();
+            layers.extend(tables);
+            if let Some(base) = base {
+                if inherits.is_some() {
+                    bail!(
+                        "the `{}` profile can't inherit from another profile",
+                        current
+                    );
+                }
+                break base;
+            }
+            let parent =
+                inherits.or_else(|| self.workspace.cargo_profile_inherits.get(&current).cloned());
+            match parent {
+                Some(parent) => {
+                    names.push(current);
+                    current = parent;
+                }
+                None if !defined && !names.is_empty() => bail!(
+                    "profile `{}` inherits from `{}`, which is not defined",
+                    names.last().unwrap(),
+                    current
+                ),
+                None => break CargoWasmPackProfile::default_custom(),
+            }
+        };
+
+        let mut resolved = base;
+        let mut sources = BTreeMap::new();
+        for (source, table) in layers.into_iter().rev() {
+            for (key, value) in table.entries() {
+                if value.is_some() {
+                    sources.insert(key, source.clone());
+                }
+            }
+            let mut profile = table.clone();
+            profile.update_with_defaults(&resolved);
+            resolved = profile;
+        }
+        resolved.sources = sources;
+        Ok(resolved)
+    }
+
+    /// Get the `[[package.metadata.wasm-js.output]]` entries of the package.
+    pub fn outputs(&self) -> &[CargoWasmPackOutput] {
+        &self.manifest.package.metadata.wasm_js.output
+    }
+
+    /// Get the settings of `output`, on top of the configured `profile`.
+    pub fn output_profile(
+        &self,
+        profile: BuildProfile,
+        output: &CargoWasmPackOutput,
+    ) -> Result<CargoWasmPackProfile> {
+        let resolved = self.configured_profile(profile)?;
+        let mut sources = resolved.sources.clone();
+        for (key, value) in output.profile.entries() {
+            if value.is_some() {
+                sources.insert(key, SettingSource::Output(output.out_name.clone()));
+            }
+        }
+        let mut profile = output.profile.clone();
+        profile.update_with_defaults(&resolved);
+        profile.sources = sources;
+        Ok(profile)
+    }
+
+    /// Get the value of a setting outside of the profiles from the package
+    /// metadata, or else from the workspace metadata.
+    fn setting<T>(&self, get: impl Fn(&CargoWasmPack) -> Option<T>) -> Option<(T, SettingSource)> {
+        get(&self.manifest.package.metadata.wasm_js)
+            .map(|value| (value, SettingSource::Package(None)))
+            .or_else(|| {
+                get(&self.workspace.wasm_js).map(|value| (value, SettingSource::Workspace(None)))
+            })
+    }
+
+    /// Get the configured `out-dir`, relative to the manifest that sets it.
+    pub fn out_dir(&self) -> Option<(PathBuf, SettingSource)> {
+        let (out_dir, source) = self.setting(|wasm_js| wasm_js.out_dir.clone())?;
+        let base = match source {
+            SettingSource::Package(_) => self.manifest_dir(),
+            _ => self.workspace_root(),
+        };
+        Some((base.join(out_dir), source))
+    }
+
+    /// Get the configured `out-name`, if any, either from the command line or
+    /// from the package metadata.
+    pub fn out_name(&self) -> Option<&str> {
+        self.out_name.as_deref()
+    }
+
+    /// Get the configured `mode`.
+    pub fn mode(&self) -> Option<(InstallMode, SettingSource)> {
+        self.setting(|wasm_js| wasm_js.mode)
+    }
+
+    /// Get the configured `target`.
+    pub fn target(&self) -> Option<(String, SettingSource)> {
+        self.setting(|wasm_js| wasm_js.target.clone())
+    }
+
+    /// Get the configured `cdylib` value.
+    pub fn cdylib(&self) -> Option<(bool, SettingSource)> {
+        self.setting(|wasm_js| wasm_js.cdylib)
+    }
+
+    /// Get the configured `weak-refs` value.
+    pub fn weak_refs(&self) -> Option<(bool, SettingSource)> {
+        self.setting(|wasm_js| wasm_js.weak_refs)
+    }
+
+    /// Get the configured `reference-types` value.
+    pub fn reference_types(&self) -> Option<(bool, SettingSource)> {
+        self.setting(|wasm_js| wasm_js.reference_types)
+    }
+
+    /// Check that `rustc_version` satisfies the package's `rust-version`.
+    pub fn check_rust_version(&self, rustc_version: &Version) -> Result<()> {
+        let rust_version = match &self.pkg().rust_version {
+            Some(rust_version) => rust_version,
+            None => return Ok(()),
+        };
+        // Nightly and beta builds count as the release they lead up to
+        let release = Version::new(
+            rustc_version.major,
+            rustc_version.minor,
+            rustc_version.patch,
+        );
+        if !rust_version.matches(&release) {
+            bail!(
+                "package `{}` requires rustc {} or newer, according to its `rust-version`, but rustc {} is being used. Update it with `rustup update`, or pick a newer toolchain with `--toolchain`.",
+                self.package_name(),
+                rust_version.to_string().trim_start_matches('^'),
+                rustc_version
+            );
         }
+        Ok(())
     }
 
-    /// Check that the crate the given path is properly configured.
-    pub fn check_crate_config(&self) -> Result<()> {
-        self.check_crate_type()?;
+    /// Check that the crate the given path is properly configured. With
+    /// `as_cdylib`, any library crate will do.
+    pub fn check_crate_config(&self, as_cdylib: bool) -> Result<()> {
+        self.check_crate_type(as_cdylib)?;
         Ok(())
     }
 
-    fn check_crate_type(&self) -> Result<()> {
+    fn check_crate_type(&self, as_cdylib: bool) -> Result<()> {
         let pkg = &self.data.packages[self.current_idx];
-        let any_cdylib = pkg
-            .targets
-            .iter()
-            .filter(|target| target.kind.iter().any(|k| k == "cdylib"))
-            .any(|target| target.crate_types.iter().any(|s| s == "cdylib"));
-        if any_cdylib {
+        if as_cdylib {
+            if self.lib_target().is_some() {
+                return Ok(());
+            }
+            bail!("package `{}` has no library target", pkg.name);
+        }
+        if self.has_cdylib() {
             return Ok(());
         }
         bail!(
-            "crate-type must be cdylib to compile to wasm32-unknown-unknown. Add the following to your \
+            "crate-type must be cdylib to compile to WebAssembly. Add the following to your \
              Cargo.toml file:\n\n\
              [lib]\n\
-             crate-type = [\"cdylib\", \"rlib\"]"
+             crate-type = [\"cdylib\", \"rlib\"]\n\n\
+             or build it with `--cdylib`, or `cdylib = true` in `[package.metadata.wasm-js]`."
         )
     }
 
+    /// Whether the package declares a cdylib library.
+    pub fn has_cdylib(&self) -> bool {
+        self.pkg()
+            .targets
+            .iter()
+            .filter(|target| target.kind.iter().any(|k| k == "cdylib"))
+            .any(|target| target.crate_types.iter().any(|s| s == "cdylib"))
+    }
+
+    /// Whether the package has an example target named `name`.
+    pub fn has_example(&self, name: &str) -> bool {
+        self.example_target(name).is_some()
+    }
+
+    /// Check that the example target `name` can be compiled to WebAssembly.
+    pub fn check_example(&self, name: &str) -> Result<()> {
+        let example = self.example_target(name).ok_or_else(|| {
+            anyhow!(
+                "package `{}` has no example named `{}`",
+                self.package_name(),
+                name
+            )
+        })?;
+        if !example.crate_types.iter().any(|s| s == "cdylib") {
+            bail!(
+                "crate-type must be cdylib to compile the example `{}` to WebAssembly. Add the following to your \
+                 Cargo.toml file:\n\n\
+                 [[example]]\n\
+                 name = \"{}\"\n\
+                 crate-type = [\"cdylib\"]",
+                name,
+                name
+            );
+        }
+        Ok(())
+    }
+
+    fn example_target(&self, name: &str) -> Option<&cargo_metadata::Target> {
+        self.pkg()
+            .targets
+            .iter()
+            .find(|target| target.name == name && target.kind.iter().any(|k| k == "example"))
+    }
+
+    /// Get the library target of the package, whatever its crate types.
+    fn lib_target(&self) -> Option<&cargo_metadata::Target> {
+        self.pkg().targets.iter().find(|target| {
+            target.kind.iter().any(|k| {
+                matches!(
+                    k.as_str(),
+                    "lib" | "rlib" | "dylib" | "cdylib" | "staticlib"
+                )
+            })
+        })
+    }
+
+    /// Check that each of `features` is a feature of the crate, or names a
+    /// feature of one of its dependencies as `dependency/feature`.
+    pub fn check_features(&self, features: &[String]) -> Result<()> {
+        let pkg = self.pkg();
+        for feature in features {
+            if let Some((dep, _)) = feature.split_once('/') {
+                let dep = dep.trim_end_matches('?');
+                if pkg.dependencies.iter().any(|d| {
+                    d.rename.as_ref().unwrap_or(&d.name).replace('-', "_") == dep.replace('-', "_")
+                }) {
+                    continue;
+                }
+                bail!(
+                    "feature `{}` refers to `{}`, which is not a dependency of package `{}`",
+                    feature,
+                    dep,
+                    pkg.name
+                )
+            }
+            if feature == "default" || pkg.features.contains_key(feature) {
+                continue;
+            }
+            let closest = pkg
+                .features
+                .keys()
+                .map(|name| (levenshtein(name, feature), name))
+                .min();
+            match closest {
+                Some((distance, name)) if distance <= 2 => bail!(
+                    "package `{}` does not have the feature `{}`\n\nDid you mean `{}`?",
+                    pkg.name,
+                    feature,
+                    name
+                ),
+                Some(_) => bail!(
+                    "package `{}` does not have the feature `{}`\n\nAvailable features: {}",
+                    pkg.name,
+                    feature,
+                    pkg.features
+                        .keys()
+                        .map(|name| name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                None => bail!(
+                    "package `{}` does not have the feature `{}`, because it has no features",
+                    pkg.name,
+                    feature
+                ),
+            }
+        }
+        Ok(())
+    }
+
     fn pkg(&self) -> &cargo_metadata::Package {
         &self.data.packages[self.current_idx]
     }
 
+    /// Get the cargo package name of the crate.
+    pub fn package_name(&self) -> &str {
+        &self.pkg().name
+    }
+
+    /// Get the cargo package id of the crate.
+    pub fn package_id(&self) -> &cargo_metadata::PackageId {
+        &self.pkg().id
+    }
+
     /// Get the crate name for the crate at the given path.
     pub fn crate_name(&self) -> String {
-        let pkg = self.pkg();
-        match pkg
-            .targets
-            .iter()
-            .find(|t| t.kind.iter().any(|k| k == "cdylib"))
-        {
+        match self.lib_target() {
             Some(lib) => lib.name.replace("-", "_"),
-            None => pkg.name.replace("-", "_"),
+            None => self.pkg().name.replace("-", "_"),
         }
     }
 
@@ -442,4 +1333,12 @@ impl CrateData {
     pub fn workspace_root(&self) -> &Path {
         Path::new(&self.data.workspace_root)
     }
+
+    /// Get the directory of the package's `Cargo.toml`.
+    pub fn manifest_dir(&self) -> &Path {
+        self.pkg()
+            .manifest_path
+            .parent()
+            .map_or(self.workspace_root(), |dir| dir.as_std_path())
+    }
 }
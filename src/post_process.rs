@@ -0,0 +1,61 @@
+//! Running the user's own `post-process` commands over the wasm before it is
+//! embedded
+
+use crate::child;
+use crate::PBAR;
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::Path;
+use wasmparser::{Validator, WasmFeatures};
+
+/// The placeholder in a command's arguments that is replaced by the path of
+/// the wasm to transform.
+pub const WASM_PLACEHOLDER: &str = "{wasm}";
+
+/// What a `post-process` command gets to know about the module it transforms.
+pub struct Input<'a> {
+    /// The wasm to transform in place.
+    pub wasm_path: &'a Path,
+    /// The directory of the package, where the commands run.
+    pub package_dir: &'a Path,
+    /// The name of the package.
+    pub package_name: &'a str,
+    /// The prefix of the file names of the module.
+    pub out_name: &'a str,
+}
+
+/// Run each of `commands`, in order, over the wasm of `input`. The path of
+/// the wasm replaces [WASM_PLACEHOLDER] in their arguments, and is also in the
+/// `WASM_JS_WASM_PATH` environment variable. After each command, the wasm must
+/// still be a valid module.
+pub fn run(commands: &[Vec<String>], input: &Input) -> Result<()> {
+    let wasm_path = input
+        .wasm_path
+        .to_str()
+        .ok_or_else(|| anyhow!("path contains non-UTF-8 characters"))?;
+    for command in commands {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| anyhow!("`post-process` has an empty command"))?;
+        let line = command.join(" ");
+        PBAR.info(&format!("Post-processing wasm with `{}`...", line));
+
+        let mut cmd = child::new_command(program);
+        cmd.args(
+            args.iter()
+                .map(|arg| arg.replace(WASM_PLACEHOLDER, wasm_path)),
+        )
+        .current_dir(input.package_dir)
+        .env("WASM_JS_WASM_PATH", input.wasm_path)
+        .env("WASM_JS_PACKAGE_NAME", input.package_name)
+        .env("WASM_JS_OUT_NAME", input.out_name);
+        child::run(cmd, program).with_context(|| format!("Running `{}`", line))?;
+
+        let wasm = fs::read(input.wasm_path)
+            .with_context(|| format!("`{}` didn't leave a wasm module behind", line))?;
+        Validator::new_with_features(WasmFeatures::all())
+            .validate_all(&wasm)
+            .with_context(|| format!("`{}` left an invalid wasm module", line))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,282 @@
+//! Running a module's start function at build time with `wizer`, which saves
+//! the state it leaves behind into the module
+
+use crate::child;
+use crate::custom_sections::MODULE_HEADER;
+use crate::install::{self, Tool};
+use crate::PBAR;
+use anyhow::{bail, Context, Result};
+use binary_install::Cache;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use wasmparser::{
+    ExternalKind, FuncType, Parser, Payload, RefType, TypeRef, ValType, Validator, WasmFeatures,
+};
+
+/// The version of `wizer` that we install if it isn't found on the `PATH`
+const WIZER_VERSION: &str = "6.0.0";
+
+/// The function that is run at build time. wizer removes its export, so the
+/// loader won't find it in the pre-initialized module, and won't run it again.
+pub const INIT_FUNC: &str = "__wbindgen_start";
+
+/// The features that wizer is run with: the ones of WebAssembly 2.0, which
+/// include SIMD, but not relaxed SIMD.
+const WIZER_FEATURES: WasmFeatures = WasmFeatures::WASM2;
+
+/// The JS import that `__wbindgen_start` calls first when reference types are
+/// enabled. It only sets up the JS side of the externref table, so it does
+/// nothing at build time, and the loader calls it instead of the start
+/// function.
+const INIT_EXTERNREF_TABLE: &str = "__wbindgen_init_externref_table";
+
+/// Run `__wbindgen_start` of the wasm at `wasm_path` at build time with
+/// `wizer`, downloading it into `cache` if necessary, and replace the wasm
+/// with one that starts out in the state that the start function left behind.
+///
+/// JS imports aren't available at build time, so wizer links the module
+/// against stubs that trap when they are called.
+pub fn run(cache: &Cache, wasm_path: &Path, install_permitted: bool) -> Result<()> {
+    let wasm = fs::read(wasm_path)?;
+    check_features(&wasm).with_context(|| format!("Pre-initializing {}", wasm_path.display()))?;
+    let stubs =
+        import_stubs(&wasm).with_context(|| format!("Pre-initializing {}", wasm_path.display()))?;
+
+    let status = install::download_prebuilt_or_cargo_install(
+        Tool::Wizer,
+        cache,
+        WIZER_VERSION,
+        install_permitted,
+    )?;
+    let wizer_path =
+        install::get_tool_path(&status, Tool::Wizer)?.binary(&Tool::Wizer.to_string())?;
+
+    PBAR.info(&format!(
+        "Pre-initializing wasm by running `{}` with `wizer`...",
+        INIT_FUNC
+    ));
+
+    let tmp = wasm_path.with_extension("wizer.wasm");
+    let mut cmd = Command::new(&wizer_path);
+    cmd.arg(wasm_path)
+        .arg("-o")
+        .arg(&tmp)
+        .arg("--init-func")
+        .arg(INIT_FUNC)
+        .arg("--wasm-bulk-memory")
+        .arg("true")
+        .arg("--wasm-reference-types")
+        .arg("true")
+        .arg("--wasm-simd")
+        .arg("true");
+    let mut stub_paths = Vec::new();
+    for (i, (module, stub)) in stubs.iter().enumerate() {
+        let stub_path = wasm_path.with_extension(format!("imports{}.wasm", i));
+        fs::write(&stub_path, stub)?;
+        cmd.arg("--preload")
+            .arg(format!("{}={}", module, stub_path.display()));
+        stub_paths.push(stub_path);
+    }
+    let result = child::run(cmd, "wizer").with_context(|| {
+        format!(
+            "Running `{}` at build time failed. Javascript imports aren't available at build time, so calling one traps.",
+            INIT_FUNC
+        )
+    });
+    for stub_path in stub_paths {
+        drop(fs::remove_file(stub_path));
+    }
+    result?;
+    fs::rename(&tmp, wasm_path)?;
+    Ok(())
+}
+
+/// Check that the wasm module `wasm` only uses [WIZER_FEATURES]. Target
+/// features can be enabled by the profile, but also by rustflags from the
+/// environment or cargo's config, so the module itself is checked.
+pub fn check_features(wasm: &[u8]) -> Result<()> {
+    if let Err(e) = Validator::new_with_features(WIZER_FEATURES).validate_all(wasm) {
+        bail!(
+            "wizer can only pre-initialize wasm that uses the WebAssembly 2.0 features, which include SIMD but not relaxed SIMD, but the module doesn't validate with just those: {}\n\
+             Turn off the other target features in `target-features`, `rustflags`, `RUSTFLAGS` or the rustflags of cargo's config.",
+            e
+        );
+    }
+    Ok(())
+}
+
+/// Build a module for each module that the wasm module `wasm` imports from,
+/// which wizer can link in its place. Each one is returned with the name that
+/// it is imported by.
+pub fn import_stubs(wasm: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut types = Vec::new();
+    let mut imports: Vec<(String, Vec<(String, FuncType)>)> = Vec::new();
+    let mut exports_init = false;
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::TypeSection(reader) => {
+                for ty in reader.into_iter_err_on_gc_types() {
+                    types.push(ty?);
+                }
+            }
+            Payload::ImportSection(reader) => {
+                for import in reader.into_imports() {
+                    let import = import?;
+                    let ty = match import.ty {
+                        TypeRef::Func(index) => types
+                            .get(index as usize)
+                            .cloned()
+                            .context("an import has a type that doesn't exist")?,
+                        _ => bail!(
+                            "the module imports `{}` from `{}`, which isn't available at build time",
+                            import.name,
+                            import.module
+                        ),
+                    };
+                    let entry = match imports.iter().position(|(m, _)| m == import.module) {
+                        Some(position) => &mut imports[position].1,
+                        None => {
+                            imports.push((import.module.to_string(), Vec::new()));
+                            &mut imports.last_mut().unwrap().1
+                        }
+                    };
+                    entry.push((import.name.to_string(), ty));
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    exports_init |= export.name == INIT_FUNC && export.kind == ExternalKind::Func;
+                }
+            }
+            _ => {}
+        }
+    }
+    if !exports_init {
+        bail!(
+            "the module doesn't export `{}`, so there is nothing to run at build time. \
+             wasm-bindgen only generates it for a `#[wasm_bindgen(start)]` function, \
+             or when reference types are enabled.",
+            INIT_FUNC
+        );
+    }
+    imports
+        .into_iter()
+        .map(|(module, functions)| {
+            if module.contains('=') {
+                bail!("wizer can't link imports from `{}`", module);
+            }
+            let stub = stub_module(&module, &functions)?;
+            Ok((module, stub))
+        })
+        .collect()
+}
+
+const TYPE_SECTION_ID: u8 = 1;
+const FUNCTION_SECTION_ID: u8 = 3;
+const EXPORT_SECTION_ID: u8 = 7;
+const CODE_SECTION_ID: u8 = 10;
+
+/// A module named `name` that exports each of `functions`. They trap when they
+/// are called, except for [INIT_EXTERNREF_TABLE], which does nothing. They are
+/// named after the imports, so that the backtrace of a trap says which import
+/// was called.
+fn stub_module(name: &str, functions: &[(String, FuncType)]) -> Result<Vec<u8>> {
+    let mut module = MODULE_HEADER.to_vec();
+
+    let mut types = Vec::new();
+    write_u32(&mut types, functions.len() as u32);
+    for (_, ty) in functions {
+        types.push(0x60);
+        for val_types in [ty.params(), ty.results()] {
+            write_u32(&mut types, val_types.len() as u32);
+            for &val_type in val_types {
+                types.push(encode_val_type(val_type)?);
+            }
+        }
+    }
+    write_section(&mut module, TYPE_SECTION_ID, &types);
+
+    let mut indices = Vec::new();
+    write_u32(&mut indices, functions.len() as u32);
+    for i in 0..functions.len() {
+        write_u32(&mut indices, i as u32);
+    }
+    write_section(&mut module, FUNCTION_SECTION_ID, &indices);
+
+    let mut exports = Vec::new();
+    write_u32(&mut exports, functions.len() as u32);
+    for (i, (function, _)) in functions.iter().enumerate() {
+        write_name(&mut exports, function);
+        exports.push(0x00);
+        write_u32(&mut exports, i as u32);
+    }
+    write_section(&mut module, EXPORT_SECTION_ID, &exports);
+
+    let mut code = Vec::new();
+    write_u32(&mut code, functions.len() as u32);
+    for (function, _) in functions {
+        // No locals, then `unreachable` unless the function does nothing
+        let body: &[u8] = if function == INIT_EXTERNREF_TABLE {
+            &[0x00, 0x0b]
+        } else {
+            &[0x00, 0x00, 0x0b]
+        };
+        write_u32(&mut code, body.len() as u32);
+        code.extend_from_slice(body);
+    }
+    write_section(&mut module, CODE_SECTION_ID, &code);
+
+    let mut names = Vec::new();
+    write_name(&mut names, "name");
+    let mut module_name = Vec::new();
+    write_name(&mut module_name, name);
+    write_section(&mut names, 0, &module_name);
+    let mut function_names = Vec::new();
+    write_u32(&mut function_names, functions.len() as u32);
+    for (i, (function, _)) in functions.iter().enumerate() {
+        write_u32(&mut function_names, i as u32);
+        write_name(&mut function_names, function);
+    }
+    write_section(&mut names, 1, &function_names);
+    write_section(&mut module, 0, &names);
+
+    Ok(module)
+}
+
+fn encode_val_type(val_type: ValType) -> Result<u8> {
+    Ok(match val_type {
+        ValType::I32 => 0x7f,
+        ValType::I64 => 0x7e,
+        ValType::F32 => 0x7d,
+        ValType::F64 => 0x7c,
+        ValType::V128 => 0x7b,
+        ValType::Ref(RefType::FUNCREF) => 0x70,
+        ValType::Ref(RefType::EXTERNREF) => 0x6f,
+        ValType::Ref(_) => bail!("an import has a parameter or result of an unsupported type"),
+    })
+}
+
+fn write_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_u32(out, name.len() as u32);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn write_section(out: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    out.push(id);
+    write_u32(out, contents.len() as u32);
+    out.extend_from_slice(contents);
+}
@@ -1,16 +1,18 @@
 //! Testing a Rust crate compiled to wasm.
+use crate::build;
 use crate::child;
 use crate::PBAR;
 use anyhow::{Context, Result};
 use std::ffi::OsStr;
 use std::path::Path;
-use std::process::Command;
 
-/// Run `cargo test` with the `nightly` toolchain and targeting
-/// `wasm32-unknown-unknown`.
+/// Run `cargo test` targeting `target`, with `toolchain` instead of the one
+/// that rustup picks, if given.
 pub fn cargo_test_wasm<I, K, V>(
     path: &Path,
     release: bool,
+    target: &str,
+    toolchain: Option<&str>,
     envs: I,
     extra_options: &[String],
 ) -> Result<()>
@@ -19,10 +21,10 @@ where
     K: AsRef<OsStr>,
     V: AsRef<OsStr>,
 {
-    let mut cmd = Command::new("cargo");
+    let mut cmd = build::rust_command("cargo", path, toolchain);
 
     cmd.envs(envs);
-    cmd.current_dir(path).arg("test");
+    cmd.arg("test");
 
     if PBAR.quiet() {
         cmd.arg("--quiet");
@@ -32,7 +34,7 @@ where
         cmd.arg("--release");
     }
 
-    cmd.arg("--target").arg("wasm32-unknown-unknown");
+    cmd.arg("--target").arg(target);
 
     cmd.args(extra_options);
 
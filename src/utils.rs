@@ -2,7 +2,9 @@
 use anyhow::Result;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
+use siphasher::sip::SipHasher13;
 use std::fs::{self, File};
+use std::hash::Hasher;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
@@ -66,16 +68,24 @@ pub fn elapsed(duration: Duration) -> String {
     }
 }
 
-/// Reads a file from `input_path` and returns its contents compressed using DEFLATE
-/// as an in-memory vector of bytes (`Vec<u8>`).
-pub fn read_and_compress<W: Write>(out: W, input_path: &Path) -> Result<()> {
-    let mut encoder = ZlibEncoder::new(out, Compression::best());
+/// Reads a file from `input_path` and writes its contents to `out`, compressed
+/// using DEFLATE at the zlib compression `level`.
+pub fn read_and_compress<W: Write>(out: W, input_path: &Path, level: u32) -> Result<()> {
+    let mut encoder = ZlibEncoder::new(out, Compression::new(level));
     let mut input_file = File::open(input_path)?;
     io::copy(&mut input_file, &mut encoder)?;
     encoder.finish()?;
     Ok(())
 }
 
+/// Computes an identifier for the wasm binary at `input_path`, so that
+/// generated code can tell builds apart.
+pub fn build_id(input_path: &Path) -> Result<String> {
+    let mut hasher = SipHasher13::new();
+    hasher.write(&fs::read(input_path)?);
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
 pub trait StrUtils {
     fn to_os_bytes(self: &Self) -> Vec<u8>;
 }
@@ -0,0 +1,103 @@
+//! Support for downloading and executing `wasm2js`
+
+use crate::child;
+use crate::install;
+use crate::PBAR;
+use anyhow::{bail, Result};
+use binary_install::Cache;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use wasmparser::{Validator, WasmFeatures};
+
+/// The features beyond the MVP that `wasm2js` knows how to translate. rustc's
+/// default target features for wasm32 also include multivalue and
+/// reference-types, which it can't translate, so the input is checked against
+/// [WASM2JS_FEATURES] first.
+const FEATURE_ARGS: &[&str] = &[
+    "--enable-bulk-memory",
+    "--enable-mutable-globals",
+    "--enable-nontrapping-float-to-int",
+    "--enable-sign-ext",
+];
+
+/// The features of [FEATURE_ARGS] for validation. LLVM encodes the table index
+/// of `call_indirect` with padding when reference-types is enabled, which
+/// `wasm2js` reads without needing the feature.
+const WASM2JS_FEATURES: WasmFeatures = WasmFeatures::WASM1
+    .union(WasmFeatures::BULK_MEMORY)
+    .union(WasmFeatures::SATURATING_FLOAT_TO_INT)
+    .union(WasmFeatures::SIGN_EXTENSION)
+    .union(WasmFeatures::CALL_INDIRECT_OVERLONG);
+
+/// Translate the wasm binary at `wasm_path` into the Javascript module at
+/// `out_path`, downloading `wasm2js` into `cache` if necessary. Passes `args`
+/// to `wasm2js`.
+pub fn run(
+    cache: &Cache,
+    wasm_path: &Path,
+    out_path: &Path,
+    args: &[String],
+    install_permitted: bool,
+) -> Result<()> {
+    check_features(wasm_path)?;
+    let wasm2js_path = match find_wasm2js(cache, install_permitted)? {
+        Some(path) => path,
+        None => bail!("wasm2js is not available, so no Javascript fallback can be generated"),
+    };
+
+    PBAR.info("Translating wasm to Javascript with `wasm2js`...");
+
+    let mut cmd = Command::new(&wasm2js_path);
+    cmd.arg(wasm_path)
+        .arg("-o")
+        .arg(out_path)
+        .args(FEATURE_ARGS)
+        .args(args);
+    child::run(cmd, "wasm2js")?;
+    Ok(())
+}
+
+/// Check that the wasm at `wasm_path` only uses features that `wasm2js` can
+/// translate, since it doesn't always say so itself.
+fn check_features(wasm_path: &Path) -> Result<()> {
+    let wasm = fs::read(wasm_path)?;
+    if let Err(e) = Validator::new_with_features(WASM2JS_FEATURES).validate_all(&wasm) {
+        bail!(
+            "wasm2js can only translate wasm that uses bulk memory, mutable globals, non-trapping float-to-int conversions and sign extension on top of the MVP, but {} doesn't validate with just those: {}\n\
+             rustc enables multivalue and reference-types by default, and wasm-bindgen then keeps JS values in an externref table. \
+             Leave `reference-types` off, and build with `-Ctarget-cpu=mvp` and a standard library that is built the same way, with `-Zbuild-std=panic_abort,std` on nightly.",
+            wasm_path.display(),
+            e
+        );
+    }
+    Ok(())
+}
+
+/// Attempts to find `wasm2js` in `PATH` locally, or failing that downloads a
+/// precompiled binary.
+///
+/// Returns `Some` if a binary was found or it was successfully downloaded.
+/// Returns `None` if a binary wasn't found in `PATH` and this platform doesn't
+/// have precompiled binaries. Returns an error if we failed to download the
+/// binary.
+pub fn find_wasm2js(cache: &Cache, install_permitted: bool) -> Result<Option<PathBuf>> {
+    // First attempt to look up in PATH. If found assume it works.
+    if let Ok(path) = which::which("wasm2js") {
+        PBAR.info(&format!("found wasm2js at {:?}", path));
+        return Ok(Some(path));
+    }
+
+    match install::download_prebuilt(&install::Tool::Wasm2Js, cache, "latest", install_permitted)? {
+        install::Status::Found(download) => Ok(Some(download.binary("bin/wasm2js")?)),
+        install::Status::CannotInstall => {
+            PBAR.info("Skipping wasm2js as no downloading was requested");
+            Ok(None)
+        }
+        install::Status::PlatformNotSupported => {
+            PBAR.info("Skipping wasm2js because it is not supported on this platform");
+            Ok(None)
+        }
+    }
+}
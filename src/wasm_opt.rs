@@ -2,16 +2,44 @@
 
 use crate::child;
 use crate::install;
+use crate::utils::read_and_compress;
 use crate::PBAR;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use binary_install::Cache;
+use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
+use std::thread;
 
-/// Execute `wasm-opt` over wasm binaries found in `out_dir`, downloading if
-/// necessary into `cache`. Passes `args` to each invocation of `wasm-opt`.
-pub fn run(cache: &Cache, out_dir: &Path, args: &[String], install_permitted: bool) -> Result<()> {
+/// The sets of flags that `wasm-opt = "auto"` tries.
+pub const AUTO_CANDIDATES: &[&[&str]] = &[
+    &["-O"],
+    &["-O", "--converge"],
+    &["-Os"],
+    &["-Os", "--converge"],
+    &["-Oz"],
+    &["-Oz", "--converge"],
+    &["-O3"],
+    &["-O3", "--converge"],
+];
+
+/// The result of running `wasm-opt` with one of the candidates of [tune].
+pub struct Trial {
+    /// The candidate flags.
+    pub flags: Vec<String>,
+    /// The size of the optimized wasm once it is compressed for embedding.
+    pub compressed_size: usize,
+}
+
+/// Execute `wasm-opt` over the wasm binary at `wasm_path`, downloading if
+/// necessary into `cache`. Passes `args` to the invocation of `wasm-opt`.
+pub fn run(
+    cache: &Cache,
+    wasm_path: &Path,
+    args: &[String],
+    install_permitted: bool,
+) -> Result<()> {
     let wasm_opt_path = match find_wasm_opt(cache, install_permitted)? {
         Some(path) => path,
         // `find_wasm_opt` will have already logged a message about this, so we don't need to here.
@@ -20,21 +48,138 @@ pub fn run(cache: &Cache, out_dir: &Path, args: &[String], install_permitted: bo
 
     PBAR.info("Optimizing wasm binaries with `wasm-opt`...");
 
-    for file in out_dir.read_dir()? {
-        let file = file?;
-        let path = file.path();
-        if path.extension().and_then(|s| s.to_str()) != Some("wasm") {
-            continue;
+    let tmp = wasm_path.with_extension("wasm-opt.wasm");
+    let mut cmd = Command::new(&wasm_opt_path);
+    cmd.arg(wasm_path).arg("-o").arg(&tmp).args(args);
+    child::run(cmd, "wasm-opt")?;
+    std::fs::rename(&tmp, wasm_path)?;
+
+    Ok(())
+}
+
+/// Execute `wasm-opt` over the wasm binary at `wasm_path` once for each of the
+/// `candidates`, in parallel, followed by `args`. The result that is the
+/// smallest after compressing it at `compression_level` replaces the binary.
+///
+/// Returns the trials sorted by compressed size, so the first one was kept, or
+/// `None` when `wasm-opt` isn't available.
+pub fn tune(
+    cache: &Cache,
+    wasm_path: &Path,
+    candidates: &[&[&str]],
+    args: &[String],
+    compression_level: u32,
+    install_permitted: bool,
+) -> Result<Option<Vec<Trial>>> {
+    let wasm_opt_path = match find_wasm_opt(cache, install_permitted)? {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    PBAR.info(&format!(
+        "Optimizing wasm binaries with {} sets of `wasm-opt` flags...",
+        candidates.len()
+    ));
+
+    let outputs: Vec<PathBuf> = (0..candidates.len())
+        .map(|i| wasm_path.with_extension(format!("wasm-opt-{}.wasm", i)))
+        .collect();
+    let results: Vec<Result<usize>> = thread::scope(|scope| {
+        let handles: Vec<_> = candidates
+            .iter()
+            .zip(&outputs)
+            .map(|(flags, tmp)| {
+                let wasm_opt_path = &wasm_opt_path;
+                scope.spawn(move || -> Result<usize> {
+                    let mut cmd = Command::new(wasm_opt_path);
+                    cmd.arg(wasm_path)
+                        .arg("-o")
+                        .arg(tmp)
+                        .args(*flags)
+                        .args(args);
+                    child::run(cmd, "wasm-opt")?;
+                    let mut compressed = Vec::new();
+                    read_and_compress(&mut compressed, tmp, compression_level)?;
+                    Ok(compressed.len())
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("wasm-opt trial panicked")))
+            })
+            .collect()
+    });
+
+    let mut trials = Vec::new();
+    let mut error = None;
+    for ((flags, tmp), result) in candidates.iter().zip(&outputs).zip(results) {
+        match result {
+            Ok(compressed_size) => trials.push((
+                Trial {
+                    flags: flags.iter().map(|flag| flag.to_string()).collect(),
+                    compressed_size,
+                },
+                tmp,
+            )),
+            Err(e) => {
+                let _ = fs::remove_file(tmp);
+                error.get_or_insert(e);
+            }
+        }
+    }
+    if let Some(e) = error {
+        for (_, tmp) in &trials {
+            let _ = fs::remove_file(tmp);
         }
+        return Err(e);
+    }
 
-        let tmp = path.with_extension("wasm-opt.wasm");
-        let mut cmd = Command::new(&wasm_opt_path);
-        cmd.arg(&path).arg("-o").arg(&tmp).args(args);
-        child::run(cmd, "wasm-opt")?;
-        std::fs::rename(&tmp, &path)?;
+    // Sorting is stable, so ties go to the earlier candidate
+    trials.sort_by_key(|(trial, _)| trial.compressed_size);
+    for (i, (_, tmp)) in trials.iter().enumerate() {
+        if i == 0 {
+            fs::rename(tmp, wasm_path)?;
+        } else {
+            fs::remove_file(tmp)?;
+        }
     }
+    Ok(Some(trials.into_iter().map(|(trial, _)| trial).collect()))
+}
 
-    Ok(())
+/// Map wasm target features, as given to `rustc` with `-C target-feature`, to
+/// the `wasm-opt` flags that enable or disable the same features. Features
+/// that `wasm-opt` doesn't know about are skipped.
+pub fn feature_args(target_features: &[String]) -> Vec<String> {
+    target_features
+        .iter()
+        .filter_map(|feature| {
+            let (action, name) = match feature.strip_prefix('-') {
+                Some(name) => ("disable", name),
+                None => ("enable", feature.strip_prefix('+').unwrap_or(feature)),
+            };
+            let flag = match name {
+                "atomics" => "threads",
+                "bulk-memory" => "bulk-memory",
+                "exception-handling" => "exception-handling",
+                "extended-const" => "extended-const",
+                "multimemory" => "multimemory",
+                "multivalue" => "multivalue",
+                "mutable-globals" => "mutable-globals",
+                "nontrapping-fptoint" => "nontrapping-float-to-int",
+                "reference-types" => "reference-types",
+                "relaxed-simd" => "relaxed-simd",
+                "sign-ext" => "sign-ext",
+                "simd128" => "simd",
+                "tail-call" => "tail-call",
+                _ => return None,
+            };
+            Some(format!("--{}-{}", action, flag))
+        })
+        .collect()
 }
 
 /// Attempts to find `wasm-opt` in `PATH` locally, or failing that downloads a
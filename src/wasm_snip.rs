@@ -0,0 +1,52 @@
+//! Support for replacing functions with `unreachable` using `wasm-snip`
+
+use crate::child;
+use crate::install::{self, Tool};
+use crate::PBAR;
+use anyhow::Result;
+use binary_install::Cache;
+use std::path::Path;
+use std::process::Command;
+
+/// The version of `wasm-snip` that we install if it isn't found on the `PATH`
+const WASM_SNIP_VERSION: &str = "0.4.0";
+
+/// Replace the bodies of the functions in the wasm at `wasm_path` whose names
+/// match one of the `patterns` with `unreachable`, along with Rust's panicking
+/// or formatting code when `panicking_code` or `fmt_code` are set. `wasm-opt`
+/// can then remove the code that only they called.
+pub fn run(
+    cache: &Cache,
+    wasm_path: &Path,
+    patterns: &[String],
+    panicking_code: bool,
+    fmt_code: bool,
+    install_permitted: bool,
+) -> Result<()> {
+    let status = install::download_prebuilt_or_cargo_install(
+        Tool::WasmSnip,
+        cache,
+        WASM_SNIP_VERSION,
+        install_permitted,
+    )?;
+    let wasm_snip_path =
+        install::get_tool_path(&status, Tool::WasmSnip)?.binary(&Tool::WasmSnip.to_string())?;
+
+    PBAR.info("Snipping functions with `wasm-snip`...");
+
+    let tmp = wasm_path.with_extension("wasm-snip.wasm");
+    let mut cmd = Command::new(&wasm_snip_path);
+    cmd.arg(wasm_path).arg("-o").arg(&tmp);
+    for pattern in patterns {
+        cmd.arg("--pattern").arg(pattern);
+    }
+    if panicking_code {
+        cmd.arg("--snip-rust-panicking-code");
+    }
+    if fmt_code {
+        cmd.arg("--snip-rust-fmt-code");
+    }
+    child::run(cmd, "wasm-snip")?;
+    std::fs::rename(&tmp, wasm_path)?;
+    Ok(())
+}
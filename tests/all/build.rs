@@ -1,7 +1,9 @@
 use crate::utils;
 use assert_cmd::prelude::*;
+use predicates::prelude::*;
 use std::fs;
 use wasm_js::{
+    build::{config_build_rustflags, CargoOptions},
     command::{self, build::BuildOptions},
     Cli,
 };
@@ -23,7 +25,7 @@ fn it_should_build_js_hello_world_example() {
     let fixture = utils::fixture::js_hello_world();
     let mut args = Cli::from_command(command::Command::Build(BuildOptions {
         path: Some(fixture.path.clone()),
-        out_dir: "dist".into(),
+        out_dir: Some("dist".into()),
         ..Default::default()
     }));
     args.install_cache = Some(fixture.cache_dir().clone().to_string_lossy().to_string());
@@ -55,6 +57,135 @@ fn it_should_build_js_hello_world_example_with_custom_target_dir() {
         .success();
 }
 
+#[test]
+fn it_should_build_js_hello_world_example_with_inline_target_dir() {
+    let fixture = utils::fixture::js_hello_world();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--target-dir=target3")
+        .assert()
+        .success();
+    assert!(fixture.path.join("dist").join("js_hello_world.js").exists());
+}
+
+#[test]
+fn build_workspace_members() {
+    let fixture = utils::fixture::wasm_workspace();
+    fixture.install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--workspace")
+        .assert()
+        .success();
+
+    let dist = fixture.path.join("dist");
+    assert!(dist.join("codec.js").exists());
+    assert!(dist.join("parser.js").exists());
+    assert!(!dist.join("tool.js").exists());
+    // Both modules share one runtime
+    assert!(dist.join("wasm-js-runtime.js").exists());
+    let codec = fs::read_to_string(dist.join("codec.js")).unwrap();
+    assert!(codec.contains("from './wasm-js-runtime.js'"));
+    assert!(!codec.contains("DecompressionStream"));
+    // Neither module takes snapshots
+    let runtime = fs::read_to_string(dist.join("wasm-js-runtime.js")).unwrap();
+    assert!(!runtime.contains("snapshotWasm"));
+}
+
+#[test]
+fn build_workspace_members_with_workspace_metadata() {
+    let fixture = utils::fixture::wasm_workspace();
+    fixture.install_local_wasm_bindgen();
+    fixture
+        .file(
+            "Cargo.toml",
+            r#"
+                [workspace]
+                members = ["codec", "parser", "tool"]
+
+                [workspace.metadata.wasm-js.profile.release]
+                snapshot = true
+                loader-target = "es2015"
+            "#,
+        )
+        .file(
+            "parser/Cargo.toml",
+            r#"
+                [package]
+                name = "parser"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [package.metadata.wasm-js.profile.release]
+                snapshot = false
+            "#,
+        );
+    fixture
+        .wasm_js()
+        .arg("--verbose")
+        .arg("build")
+        .arg("--workspace")
+        .arg("--no-opt")
+        .assert()
+        .stderr(predicates::str::contains(
+            "# [workspace.metadata.wasm-js.profile.release]",
+        ))
+        .stderr(predicates::str::contains(
+            "# [package.metadata.wasm-js.profile.release]",
+        ))
+        .stderr(predicates::str::contains("# --no-opt"))
+        .success();
+
+    let dist = fixture.path.join("dist");
+    let codec = fs::read_to_string(dist.join("codec.js")).unwrap();
+    assert!(codec.contains("export function snapshotWasm()"));
+    let parser = fs::read_to_string(dist.join("parser.js")).unwrap();
+    assert!(!parser.contains("export function snapshotWasm()"));
+    let runtime = fs::read_to_string(dist.join("wasm-js-runtime.js")).unwrap();
+    assert!(!runtime.contains("async function"));
+    assert!(runtime.contains("export function snapshotWasm("));
+}
+
+#[test]
+fn build_selected_workspace_members() {
+    let fixture = utils::fixture::wasm_workspace();
+    fixture.install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("-p")
+        .arg("parser")
+        .assert()
+        .success();
+
+    let dist = fixture.path.join("dist");
+    assert!(!dist.join("codec.js").exists());
+    assert!(dist.join("parser.js").exists());
+}
+
+#[test]
+fn build_unknown_workspace_member_fails() {
+    let fixture = utils::fixture::wasm_workspace();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("-p")
+        .arg("lexer")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "package `lexer` is not a member of the workspace",
+        ));
+}
+
 #[test]
 fn renamed_crate_name_works() {
     let fixture = utils::fixture::Fixture::new();
@@ -308,3 +439,1734 @@ fn build_crates_with_same_names() {
         .assert()
         .success();
 }
+
+#[test]
+fn build_with_snapshot_api() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                authors = []
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "0.2"
+
+                [package.metadata.wasm-js.profile.release]
+                snapshot = true
+            "#,
+        )
+        .hello_world_src_lib()
+        .install_local_wasm_bindgen();
+    fixture.wasm_js().arg("build").assert().success();
+
+    let js = fs::read_to_string(fixture.path.join("dist/foo.js")).unwrap();
+    assert!(js.contains("export function snapshotWasm()"));
+    assert!(js.contains("export function restoreWasm(bytes)"));
+    assert!(js.contains("const BUILD_ID = '"));
+    let dts = fs::read_to_string(fixture.path.join("dist/foo.d.ts")).unwrap();
+    assert!(dts.contains("export function snapshotWasm(): Uint8Array;"));
+
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--dev")
+        .assert()
+        .success();
+    let js = fs::read_to_string(fixture.path.join("dist/foo.js")).unwrap();
+    assert!(!js.contains("snapshotWasm"));
+}
+
+#[test]
+fn snapshot_restores_memory_and_externrefs() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "0.2"
+
+                [package.metadata.wasm-js.profile.release]
+                snapshot = true
+            "#,
+        )
+        .file(
+            "src/lib.rs",
+            r#"
+                use std::cell::{Cell, RefCell};
+                use wasm_bindgen::prelude::*;
+
+                thread_local! {
+                    static COUNT: Cell<u32> = Cell::new(0);
+                    static LABEL: RefCell<JsValue> = RefCell::new(JsValue::UNDEFINED);
+                }
+
+                #[wasm_bindgen]
+                pub fn bump() -> u32 {
+                    COUNT.with(|count| {
+                        count.set(count.get() + 1);
+                        count.get()
+                    })
+                }
+
+                #[wasm_bindgen]
+                pub fn set_label(label: JsValue) {
+                    LABEL.with(|l| *l.borrow_mut() = label);
+                }
+
+                #[wasm_bindgen]
+                pub fn label() -> JsValue {
+                    LABEL.with(|l| l.borrow().clone())
+                }
+
+                #[wasm_bindgen]
+                pub fn has_label() -> JsValue {
+                    LABEL.with(|l| JsValue::from_bool(!l.borrow().is_undefined()))
+                }
+            "#,
+        )
+        .file(
+            "dist/run.mjs",
+            r#"
+                import { getWasm, snapshotWasm, restoreWasm } from './foo.js';
+                import * as foo from './foo_bg.js';
+
+                await getWasm();
+                foo.set_label('saved');
+                foo.bump();
+                const snapshot = snapshotWasm();
+                foo.bump();
+                foo.set_label('changed');
+                await restoreWasm(snapshot);
+                console.log(JSON.stringify([foo.bump(), foo.label(), foo.has_label(), foo.bump()]));
+
+                // Objects in the externref table can't be saved
+                foo.set_label({});
+                try {
+                    snapshotWasm();
+                    console.log('saved an object');
+                } catch (e) {
+                    console.log(e.message);
+                }
+            "#,
+        )
+        .install_local_wasm_bindgen();
+    fixture.wasm_js().arg("build").assert().success();
+
+    let output = std::process::Command::new("node")
+        .arg(fixture.path.join("dist/run.mjs"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some(r#"[2,"saved",true,3]"#));
+    assert!(lines
+        .next()
+        .unwrap()
+        .contains("holds a value of type object, which can't be saved in a snapshot"));
+}
+
+#[test]
+fn build_with_pre_initialize() {
+    let cargo_toml = |pre_initialize: bool| {
+        format!(
+            r#"
+                [package]
+                name = "squares"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "0.2"
+
+                [package.metadata.wasm-js.profile.release]
+                pre-initialize = {}
+            "#,
+            pre_initialize
+        )
+    };
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file("Cargo.toml", cargo_toml(false))
+        .file(
+            "src/lib.rs",
+            r#"
+                use std::cell::RefCell;
+                use wasm_bindgen::prelude::*;
+
+                thread_local! {
+                    static SQUARES: RefCell<Vec<u32>> = RefCell::new(Vec::new());
+                }
+
+                #[wasm_bindgen(start)]
+                fn init() {
+                    SQUARES.with(|s| *s.borrow_mut() = (1000..1064).map(|i| i * i).collect());
+                }
+
+                #[wasm_bindgen]
+                pub fn square(i: usize) -> u32 {
+                    SQUARES.with(|s| s.borrow()[i])
+                }
+
+                #[wasm_bindgen]
+                pub fn echo(value: JsValue) -> JsValue {
+                    value
+                }
+
+                #[wasm_bindgen]
+                pub fn yes() -> JsValue {
+                    JsValue::TRUE
+                }
+            "#,
+        )
+        .file(
+            "dist/run.mjs",
+            r#"
+                import { getWasm } from './squares.js';
+                import * as squares from './squares_bg.js';
+
+                await getWasm();
+                console.log(JSON.stringify([squares.square(3), squares.echo('x'), squares.yes()]));
+            "#,
+        )
+        .install_local_wasm_bindgen();
+    let embedded = || {
+        let js = fs::read_to_string(fixture.path.join("dist/squares.js")).unwrap();
+        utils::wasm::embedded_wasm(&js)
+    };
+    // The squares of 1000 and 1001, as they are laid out in memory
+    let squares: Vec<u8> = [1_000_000u32, 1_002_001]
+        .iter()
+        .flat_map(|square| square.to_le_bytes())
+        .collect();
+    let has_squares = |segments: &[Vec<u8>]| {
+        segments
+            .iter()
+            .any(|data| data.windows(squares.len()).any(|w| w == squares))
+    };
+
+    fixture.wasm_js().arg("build").assert().success();
+    let wasm = embedded();
+    assert!(utils::wasm::export_names(&wasm).contains(&"__wbindgen_start".to_string()));
+    let data = utils::wasm::data_segments(&wasm);
+    assert!(!has_squares(&data));
+
+    fs::write(fixture.path.join("Cargo.toml"), cargo_toml(true)).unwrap();
+    fixture.wasm_js().arg("build").assert().success();
+    let wasm = embedded();
+    assert!(!utils::wasm::export_names(&wasm).contains(&"__wbindgen_start".to_string()));
+    let initialized = utils::wasm::data_segments(&wasm);
+    assert_ne!(data, initialized);
+    assert!(has_squares(&initialized));
+
+    let output = std::process::Command::new("node")
+        .arg(fixture.path.join("dist/run.mjs"))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        r#"[1006009,"x",true]"#
+    );
+}
+
+#[test]
+fn build_with_pre_initialize_that_calls_javascript_fails() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "seed"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "0.2"
+
+                [package.metadata.wasm-js.profile.release]
+                pre-initialize = true
+            "#,
+        )
+        .file(
+            "src/lib.rs",
+            r#"
+                use std::cell::Cell;
+                use wasm_bindgen::prelude::*;
+
+                #[wasm_bindgen]
+                extern "C" {
+                    #[wasm_bindgen(js_namespace = Math)]
+                    fn random() -> f64;
+                }
+
+                thread_local! {
+                    static SEED: Cell<f64> = Cell::new(0.0);
+                }
+
+                #[wasm_bindgen(start)]
+                fn init() {
+                    SEED.with(|seed| seed.set(random()));
+                }
+
+                #[wasm_bindgen]
+                pub fn seed() -> f64 {
+                    SEED.with(|seed| seed.get())
+                }
+            "#,
+        )
+        .install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Running `__wbindgen_start` at build time failed. Javascript imports aren't available at build time",
+        ))
+        .stderr(predicates::str::contains(
+            "To disable pre-initialization, remove `pre-initialize = true`",
+        ));
+}
+
+#[test]
+fn build_with_pre_initialize_and_relaxed_simd_from_rustflags_fails() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "madd"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "0.2"
+
+                [package.metadata.wasm-js.profile.release]
+                pre-initialize = true
+                rustflags = ["-C", "target-feature=+simd128,+relaxed-simd"]
+            "#,
+        )
+        .file(
+            "src/lib.rs",
+            r#"
+                use std::arch::wasm32::*;
+                use std::cell::Cell;
+                use wasm_bindgen::prelude::*;
+
+                thread_local! {
+                    static MADD: Cell<f32> = Cell::new(0.0);
+                }
+
+                #[wasm_bindgen(start)]
+                fn init() {
+                    let v = f32x4_relaxed_madd(f32x4_splat(2.0), f32x4_splat(3.0), f32x4_splat(1.0));
+                    MADD.with(|madd| madd.set(f32x4_extract_lane::<0>(v)));
+                }
+
+                #[wasm_bindgen]
+                pub fn madd() -> f32 {
+                    MADD.with(|madd| madd.get())
+                }
+            "#,
+        )
+        .install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .env_remove("RUSTFLAGS")
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "wizer can only pre-initialize wasm that uses the WebAssembly 2.0 features",
+        ))
+        .stderr(predicates::str::contains("relaxed SIMD"));
+}
+
+#[test]
+fn build_with_wasm2js_fallback_and_snapshot_fails() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [package.metadata.wasm-js.profile.release]
+                wasm2js = true
+                snapshot = true
+            "#,
+        )
+        .hello_world_src_lib();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "`snapshot` can't be combined with `wasm2js` for foo",
+        ));
+}
+
+#[test]
+fn build_with_wasm2js_fallback_and_default_target_features_fails() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "fallback"
+                version = "0.1.0"
+                authors = []
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "0.2"
+
+                [package.metadata.wasm-js.profile.release]
+                wasm2js = true
+            "#,
+        )
+        .hello_world_src_lib()
+        .install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "wasm2js can only translate wasm that uses bulk memory, mutable globals, non-trapping float-to-int conversions and sign extension on top of the MVP",
+        ))
+        .stderr(predicates::str::contains(
+            "rustc enables multivalue and reference-types by default",
+        ));
+    assert!(!fixture.path.join("dist/fallback_wasm2js.js").exists());
+}
+
+fn features_fixture() -> utils::fixture::Fixture {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [features]
+                simd = []
+                small-alloc = []
+
+                [package.metadata.wasm-js.profile.release]
+                features = ["simd"]
+            "#,
+        )
+        .file(
+            "src/lib.rs",
+            r#"
+                use wasm_bindgen::prelude::*;
+
+                #[cfg(feature = "simd")]
+                #[wasm_bindgen]
+                pub fn simd_enabled() -> bool {
+                    true
+                }
+
+                #[cfg(feature = "small-alloc")]
+                #[wasm_bindgen]
+                pub fn small_alloc_enabled() -> bool {
+                    true
+                }
+            "#,
+        );
+    fixture
+}
+
+#[test]
+fn build_with_profile_features() {
+    let fixture = features_fixture();
+    fixture.install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--features")
+        .arg("small-alloc")
+        .assert()
+        .success();
+    let dts = fs::read_to_string(fixture.path.join("dist/foo.d.ts")).unwrap();
+    assert!(dts.contains("simd_enabled"));
+    assert!(dts.contains("small_alloc_enabled"));
+
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--dev")
+        .assert()
+        .success();
+    let dts = fs::read_to_string(fixture.path.join("dist/foo.d.ts")).unwrap();
+    assert!(!dts.contains("simd_enabled"));
+}
+
+#[test]
+fn build_with_misspelled_feature_fails() {
+    let fixture = features_fixture();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--features")
+        .arg("smid")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "package `foo` does not have the feature `smid`\n\nDid you mean `simd`?",
+        ));
+}
+
+#[test]
+fn build_with_profile_rustflags() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [lints.rust]
+                unexpected_cfgs = { level = "allow" }
+
+                [package.metadata.wasm-js.profile.release]
+                rustflags = ["--cfg", "from_profile"]
+                target-features = ["simd128"]
+            "#,
+        )
+        .file(
+            "src/lib.rs",
+            r#"
+                use wasm_bindgen::prelude::*;
+
+                #[cfg(from_profile)]
+                #[wasm_bindgen]
+                pub fn from_profile() {}
+
+                #[cfg(from_env)]
+                #[wasm_bindgen]
+                pub fn from_env() {}
+
+                #[cfg(target_feature = "simd128")]
+                #[wasm_bindgen]
+                pub fn simd128() {}
+            "#,
+        )
+        .install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .env("RUSTFLAGS", "--cfg from_env")
+        .arg("build")
+        .assert()
+        .success();
+
+    let dts = fs::read_to_string(fixture.path.join("dist/foo.d.ts")).unwrap();
+    assert!(dts.contains("from_profile"));
+    assert!(dts.contains("from_env"));
+    assert!(dts.contains("simd128"));
+}
+
+#[test]
+fn build_with_profile_rustflags_keeps_cargo_config_rustflags() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "config-rustflags"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [lints.rust]
+                unexpected_cfgs = { level = "allow" }
+
+                [package.metadata.wasm-js.profile.release]
+                rustflags = ["--cfg", "from_profile"]
+            "#,
+        )
+        .file(
+            ".cargo/config.toml",
+            r#"
+                [target.wasm32-unknown-unknown]
+                rustflags = ["--cfg", "from_config"]
+            "#,
+        )
+        .file(
+            "src/lib.rs",
+            r#"
+                use wasm_bindgen::prelude::*;
+
+                #[cfg(from_profile)]
+                #[wasm_bindgen]
+                pub fn from_profile() {}
+
+                #[cfg(from_config)]
+                #[wasm_bindgen]
+                pub fn from_config() {}
+            "#,
+        )
+        .install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .env_remove("RUSTFLAGS")
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .arg("build")
+        .assert()
+        .success();
+
+    let dts = fs::read_to_string(fixture.path.join("dist/config_rustflags.d.ts")).unwrap();
+    assert!(dts.contains("from_profile"));
+    assert!(dts.contains("from_config"));
+}
+
+#[test]
+fn build_with_profile_rustflags_keeps_cargo_config_build_rustflags() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "config-rustflags"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [lints.rust]
+                unexpected_cfgs = { level = "allow" }
+
+                [package.metadata.wasm-js.profile.release]
+                rustflags = ["--cfg", "from_profile"]
+            "#,
+        )
+        .file(
+            ".cargo/config.toml",
+            r#"
+                [build]
+                rustflags = ["--cfg", "from_config"]
+            "#,
+        )
+        .file(
+            "src/lib.rs",
+            r#"
+                use wasm_bindgen::prelude::*;
+
+                #[cfg(from_profile)]
+                #[wasm_bindgen]
+                pub fn from_profile() {}
+
+                #[cfg(from_config)]
+                #[wasm_bindgen]
+                pub fn from_config() {}
+            "#,
+        )
+        .install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .env_remove("RUSTFLAGS")
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .env_remove("CARGO_BUILD_RUSTFLAGS")
+        .arg("build")
+        .assert()
+        .success();
+
+    let dts = fs::read_to_string(fixture.path.join("dist/config_rustflags.d.ts")).unwrap();
+    assert!(dts.contains("from_profile"));
+    assert!(dts.contains("from_config"));
+}
+
+#[test]
+fn cargo_config_build_rustflags_are_kept_when_no_target_rustflags_match() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture.file(
+        ".cargo/config.toml",
+        r#"
+            [build]
+            rustflags = ["--cfg", "from_build"]
+
+            [target.'cfg(windows)']
+            rustflags = ["--cfg", "from_windows"]
+        "#,
+    );
+    let options = CargoOptions::default();
+    assert_eq!(
+        config_build_rustflags(&fixture.path, &options, &[]).unwrap(),
+        ["--cfg", "from_build"]
+    );
+
+    // cargo uses the `target` flags instead when one of them matches
+    fixture.file(
+        ".cargo/config.toml",
+        r#"
+            [build]
+            rustflags = ["--cfg", "from_build"]
+
+            [target.'cfg(target_arch = "wasm32")']
+            rustflags = ["--cfg", "from_wasm32"]
+        "#,
+    );
+    assert!(config_build_rustflags(&fixture.path, &options, &[])
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn cargo_config_build_rustflags_of_nested_configs_are_merged_like_cargo_does() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .file(
+            ".cargo/config.toml",
+            r#"
+                [build]
+                rustflags = ["--cfg", "from_outer"]
+            "#,
+        )
+        .file(
+            "inner/.cargo/config.toml",
+            r#"
+                [build]
+                rustflags = ["--cfg", "from_inner"]
+            "#,
+        );
+    let extra_options = [
+        "--config".to_string(),
+        r#"build.rustflags=["--cfg", "from_cli"]"#.to_string(),
+    ];
+    assert_eq!(
+        config_build_rustflags(
+            &fixture.path.join("inner"),
+            &CargoOptions::default(),
+            &extra_options
+        )
+        .unwrap(),
+        [
+            "--cfg",
+            "from_outer",
+            "--cfg",
+            "from_inner",
+            "--cfg",
+            "from_cli"
+        ]
+    );
+}
+
+#[test]
+fn build_profiling_keeps_function_names() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .cargo_toml("foo")
+        .file(
+            "src/lib.rs",
+            r#"
+                extern crate wasm_bindgen;
+                use wasm_bindgen::prelude::*;
+
+                #[inline(never)]
+                fn checksum_for_profiler(data: &[u8]) -> u32 {
+                    data.iter().fold(7, |sum, b| sum.rotate_left(5) ^ *b as u32)
+                }
+
+                #[wasm_bindgen]
+                pub fn checksum(data: &[u8]) -> u32 {
+                    checksum_for_profiler(data)
+                }
+            "#,
+        )
+        .install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--profiling")
+        .assert()
+        .success();
+
+    let js = fs::read_to_string(fixture.path.join("dist/foo.js")).unwrap();
+    let names = utils::wasm::function_names(&utils::wasm::embedded_wasm(&js));
+    assert!(
+        names
+            .iter()
+            .any(|name| name.contains("checksum_for_profiler")),
+        "{:?}",
+        names
+    );
+}
+
+fn named_profiles_fixture() -> utils::fixture::Fixture {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [profile.size]
+                inherits = "release"
+                opt-level = "z"
+
+                [profile.tiny]
+                inherits = "size"
+
+                [package.metadata.wasm-js.profile.release]
+                snapshot = true
+
+                [package.metadata.wasm-js.profile.size]
+                wasm-opt = false
+
+                [package.metadata.wasm-js.profile.tiny]
+                loader-target = "es2015"
+
+                [package.metadata.wasm-js.profile.loop-a]
+                inherits = "loop-b"
+
+                [package.metadata.wasm-js.profile.loop-b]
+                inherits = "loop-a"
+            "#,
+        )
+        .hello_world_src_lib();
+    fixture
+}
+
+#[test]
+fn build_named_profiles_inherit_settings() {
+    let fixture = named_profiles_fixture();
+    fixture.install_local_wasm_bindgen();
+
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--profile")
+        .arg("size")
+        .assert()
+        .stderr(predicates::str::contains("wasm-opt").not())
+        .success();
+    let js = fs::read_to_string(fixture.path.join("dist/foo.js")).unwrap();
+    assert!(js.contains("export function snapshotWasm()"));
+    let runtime = fs::read_to_string(fixture.path.join("dist/wasm-js-runtime.js")).unwrap();
+    assert!(runtime.contains("async function"));
+
+    // `tiny` only has a wasm-js table of its own, and follows cargo's
+    // inheritance from there
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--profile")
+        .arg("tiny")
+        .assert()
+        .stderr(predicates::str::contains("wasm-opt").not())
+        .success();
+    let js = fs::read_to_string(fixture.path.join("dist/foo.js")).unwrap();
+    assert!(js.contains("export function snapshotWasm()"));
+    let runtime = fs::read_to_string(fixture.path.join("dist/wasm-js-runtime.js")).unwrap();
+    assert!(!runtime.contains("async function"));
+}
+
+#[test]
+fn build_keeps_the_runtime_of_earlier_builds_working() {
+    let fixture = named_profiles_fixture();
+    fixture.install_local_wasm_bindgen();
+
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--profile")
+        .arg("tiny")
+        .assert()
+        .success();
+    // An es2020 module written next to the es2015 one mustn't replace the
+    // runtime with one that the es2015 module can't use
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--profile")
+        .arg("size")
+        .arg("--out-name")
+        .arg("bar")
+        .assert()
+        .success();
+    assert!(fixture.path.join("dist/foo.js").exists());
+    assert!(fixture.path.join("dist/bar.js").exists());
+    let runtime = fs::read_to_string(fixture.path.join("dist/wasm-js-runtime.js")).unwrap();
+    assert!(runtime.starts_with("/* wasm-js runtime for es2015 with snapshots,"));
+    assert!(!runtime.contains("async function"));
+}
+
+#[test]
+fn build_with_profile_inheritance_cycle_fails() {
+    let fixture = named_profiles_fixture();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--profile")
+        .arg("loop-a")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "profile inheritance has a cycle: loop-a -> loop-b -> loop-a",
+        ));
+}
+
+#[test]
+fn build_with_output_settings_in_metadata() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [package.metadata.wasm-js]
+                out-dir = "web/pkg"
+                out-name = "index"
+                mode = "no-install"
+                weak-refs = true
+            "#,
+        )
+        .hello_world_src_lib()
+        .install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .arg("--verbose")
+        .arg("build")
+        .assert()
+        .stderr(predicates::str::contains("weak-refs = true"))
+        .success();
+    assert!(fixture.path.join("web/pkg/index.js").exists());
+    assert!(fixture.path.join("web/pkg/index_bg.js").exists());
+    assert!(!fixture.path.join("dist").exists());
+
+    // The command line takes priority
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--out-dir")
+        .arg("dist")
+        .arg("--out-name")
+        .arg("foo")
+        .assert()
+        .success();
+    assert!(fixture.path.join("dist/foo.js").exists());
+}
+
+#[test]
+fn build_with_bindgen_settings_turned_off_on_the_command_line() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [package.metadata.wasm-js]
+                weak-refs = true
+                reference-types = true
+            "#,
+        )
+        .hello_world_src_lib()
+        .install_local_wasm_bindgen();
+    let output = fixture
+        .wasm_js()
+        .arg("--verbose")
+        .arg("build")
+        .arg("--no-weak-refs")
+        .arg("--no-reference-types")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let setting = |key: &str| {
+        stderr
+            .lines()
+            .find(|line| line.contains(&format!(" {} = ", key)))
+            .unwrap_or_else(|| panic!("no `{}` setting in {}", key, stderr))
+            .to_string()
+    };
+    assert!(setting("weak-refs").contains("weak-refs = false"));
+    assert!(setting("weak-refs").ends_with("# --no-weak-refs"));
+    assert!(setting("reference-types").contains("reference-types = false"));
+    assert!(setting("reference-types").ends_with("# --no-reference-types"));
+
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--weak-refs")
+        .arg("--no-weak-refs")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot be used with"));
+}
+
+#[test]
+fn build_with_unknown_mode_in_metadata_fails() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [package.metadata.wasm-js]
+                mode = "offline"
+            "#,
+        )
+        .hello_world_src_lib();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Unknown build mode: offline"));
+}
+
+#[test]
+fn build_into_tsconfig_out_dir() {
+    let fixture = utils::fixture::js_hello_world();
+    fixture
+        .file(
+            "tsconfig.json",
+            r#"{ "compilerOptions": { "outDir": "lib", }, }"#,
+        )
+        .install_local_wasm_bindgen();
+    // The tsconfig is only used when asked to
+    fixture.wasm_js().arg("build").assert().success();
+    assert!(fixture.path.join("dist/js_hello_world.js").exists());
+    assert!(!fixture.path.join("lib").exists());
+    fs::remove_dir_all(fixture.path.join("dist")).unwrap();
+
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--tsconfig-out-dir")
+        .assert()
+        .stderr(predicates::str::contains(
+            "from `compilerOptions.outDir` in",
+        ))
+        .success();
+    assert!(fixture.path.join("lib/js_hello_world.js").exists());
+    assert!(fixture.path.join("lib/wasm-js-runtime.js").exists());
+    assert!(!fixture.path.join("dist").exists());
+}
+
+#[test]
+fn build_with_non_wasm_target_fails() {
+    let fixture = utils::fixture::js_hello_world();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--target")
+        .arg("x86_64-unknown-linux-gnu")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "`x86_64-unknown-linux-gnu` is not a WebAssembly target",
+        ));
+}
+
+#[test]
+fn build_workspace_members_with_different_targets_fails() {
+    let fixture = utils::fixture::wasm_workspace();
+    fixture.file(
+        "Cargo.toml",
+        r#"
+            [workspace]
+            members = ["codec", "parser", "tool"]
+
+            [workspace.metadata.wasm-js]
+            target = "wasm32v1-none"
+        "#,
+    );
+    fixture.file(
+        "parser/Cargo.toml",
+        r#"
+            [package]
+            name = "parser"
+            version = "0.1.0"
+            edition = "2021"
+
+            [lib]
+            crate-type = ["cdylib"]
+
+            [package.metadata.wasm-js]
+            target = "wasm32-unknown-unknown"
+        "#,
+    );
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--workspace")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "`target` must be the same for all packages that are built together, but codec and parser differ.",
+        ));
+}
+
+#[test]
+fn build_with_newer_rust_version_fails() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+                rust-version = "1.999"
+
+                [lib]
+                crate-type = ["cdylib"]
+            "#,
+        )
+        .hello_world_src_lib();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "package `foo` requires rustc 1.999 or newer, according to its `rust-version`",
+        ));
+}
+
+#[test]
+fn build_library_that_is_not_a_cdylib() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+            "#,
+        )
+        .hello_world_src_lib()
+        .install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("crate-type must be cdylib"));
+
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--cdylib")
+        .assert()
+        .success();
+    assert!(fixture.path.join("dist/foo.js").exists());
+}
+
+#[test]
+fn build_library_as_cdylib_from_metadata() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["rlib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [package.metadata.wasm-js]
+                cdylib = true
+            "#,
+        )
+        .hello_world_src_lib()
+        .install_local_wasm_bindgen();
+    fixture.wasm_js().arg("build").assert().success();
+    assert!(fixture.path.join("dist/foo.js").exists());
+}
+
+fn examples_fixture(lib_crate_type: &str) -> utils::fixture::Fixture {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            format!(
+                r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["{}"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [[example]]
+                name = "demo-one"
+                crate-type = ["cdylib"]
+
+                [[example]]
+                name = "native"
+            "#,
+                lib_crate_type
+            ),
+        )
+        .hello_world_src_lib()
+        .file(
+            "examples/demo-one.rs",
+            r#"
+                use wasm_bindgen::prelude::*;
+
+                #[wasm_bindgen]
+                pub fn answer() -> u32 {
+                    42
+                }
+            "#,
+        )
+        .file("examples/native.rs", "fn main() {}")
+        .install_local_wasm_bindgen();
+    fixture
+}
+
+#[test]
+fn build_examples_next_to_the_library() {
+    let fixture = examples_fixture("cdylib");
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--example")
+        .arg("demo-one")
+        .assert()
+        .success();
+    let dist = fixture.path.join("dist");
+    for file in [
+        "foo.js",
+        "foo_bg.js",
+        "demo_one.js",
+        "demo_one_bg.js",
+        "demo_one.d.ts",
+    ] {
+        assert!(dist.join(file).exists(), "{} is missing", file);
+    }
+    let types = fs::read_to_string(dist.join("demo_one.d.ts")).unwrap();
+    assert!(types.contains("answer"));
+
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--example")
+        .arg("native")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "crate-type must be cdylib to compile the example `native` to WebAssembly",
+        ));
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--example")
+        .arg("missing")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "package `foo` has no example named `missing`",
+        ));
+}
+
+#[test]
+fn build_examples_of_a_library_that_is_not_a_cdylib() {
+    let fixture = examples_fixture("rlib");
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--example")
+        .arg("demo-one")
+        .assert()
+        .success();
+    assert!(fixture.path.join("dist/demo_one.js").exists());
+    assert!(!fixture.path.join("dist/foo.js").exists());
+}
+
+#[test]
+fn build_output_flavors() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [features]
+                fast = []
+
+                [[package.metadata.wasm-js.output]]
+                out-name = "web"
+                wasm-opt = ["-Oz"]
+                loader-target = "es2015"
+
+                [[package.metadata.wasm-js.output]]
+                out-name = "web_uncompressed"
+                wasm-opt = ["-Oz"]
+                compression-level = 0
+
+                [[package.metadata.wasm-js.output]]
+                out-name = "node"
+                features = ["fast"]
+                wasm-opt = ["-O3"]
+            "#,
+        )
+        .file(
+            "src/lib.rs",
+            r#"
+                use wasm_bindgen::prelude::*;
+
+                #[wasm_bindgen]
+                pub fn answer() -> u32 {
+                    42
+                }
+
+                #[cfg(feature = "fast")]
+                #[wasm_bindgen]
+                pub fn fast_answer() -> u32 {
+                    42
+                }
+            "#,
+        )
+        .install_local_wasm_bindgen();
+    let output = fixture
+        .wasm_js()
+        .arg("--verbose")
+        .arg("build")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // `web` and `web_uncompressed` share a cargo build
+    assert_eq!(stderr.matches("Compiling to Wasm...").count(), 2);
+    assert!(stderr.contains("Settings for the `node` output of foo:"));
+    assert!(stderr.contains("[[package.metadata.wasm-js.output]] node"));
+
+    let dist = fixture.path.join("dist");
+    assert!(!dist.join("foo.js").exists());
+    let node_types = fs::read_to_string(dist.join("node.d.ts")).unwrap();
+    assert!(node_types.contains("fast_answer"));
+    let web_types = fs::read_to_string(dist.join("web.d.ts")).unwrap();
+    assert!(web_types.contains("answer"));
+    assert!(!web_types.contains("fast_answer"));
+    let web = fs::metadata(dist.join("web.js")).unwrap().len();
+    let uncompressed = fs::metadata(dist.join("web_uncompressed.js"))
+        .unwrap()
+        .len();
+    assert!(web < uncompressed);
+    // The runtime works for the oldest loader target in the directory
+    let runtime = fs::read_to_string(dist.join("wasm-js-runtime.js")).unwrap();
+    assert!(!runtime.contains("async "));
+}
+
+#[test]
+fn build_output_flavors_with_the_same_out_name_fails() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [[package.metadata.wasm-js.output]]
+                out-name = "web"
+
+                [[package.metadata.wasm-js.output]]
+                out-name = "web"
+                wasm-opt = false
+            "#,
+        )
+        .hello_world_src_lib();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "there is more than one `[[package.metadata.wasm-js.output]]` with the out-name `web`",
+        ));
+}
+
+#[test]
+fn build_enhanced_target_features() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [package.metadata.wasm-js.profile.release]
+                loader-target = "es2020"
+                enhanced-target-features = ["relaxed-simd"]
+            "#,
+        )
+        .file(
+            "src/lib.rs",
+            r#"
+                use wasm_bindgen::prelude::*;
+
+                #[wasm_bindgen]
+                pub fn relaxed() -> bool {
+                    cfg!(target_feature = "relaxed-simd")
+                }
+            "#,
+        )
+        .install_local_wasm_bindgen();
+    let output = fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("Compiling to Wasm...").count(), 2);
+
+    let dist = fixture.path.join("dist");
+    let loader = fs::read_to_string(dist.join("foo.js")).unwrap();
+    assert!(loader.contains("import('./foo_baseline.js')"));
+    let baseline = fs::read_to_string(dist.join("foo_baseline.js")).unwrap();
+    assert!(baseline.contains("export const CHUNKS"));
+    assert!(!dist.join("foo_enhanced.js").exists());
+}
+
+#[test]
+fn build_enhanced_target_features_without_a_probe_fails() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [package.metadata.wasm-js.profile.release]
+                loader-target = "es2020"
+                enhanced-target-features = ["atomics"]
+            "#,
+        )
+        .hello_world_src_lib();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "The `atomics` target feature can't be detected at runtime",
+        ));
+}
+
+#[test]
+fn build_strips_custom_sections() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [package.metadata.wasm-js.profile.profiling]
+                strip-sections = ["producers", "target_features"]
+            "#,
+        )
+        .hello_world_src_lib()
+        .install_local_wasm_bindgen();
+    let output = fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--profiling")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Removed the `producers` custom section from foo, saving "));
+
+    let js = fs::read_to_string(fixture.path.join("dist/foo.js")).unwrap();
+    let sections = utils::wasm::custom_section_names(&utils::wasm::embedded_wasm(&js));
+    assert!(!sections.iter().any(|name| name == "producers"));
+    assert!(!sections.iter().any(|name| name == "target_features"));
+    assert!(sections.iter().any(|name| name == "name"), "{:?}", sections);
+}
+
+#[test]
+fn build_runs_post_process_commands() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [package.metadata.wasm-js.profile.release]
+                post-process = [
+                    ["node", "scripts/watermark.js", "{wasm}"],
+                ]
+            "#,
+        )
+        .file(
+            "scripts/watermark.js",
+            r#"
+                // Append a custom section named after the module
+                const fs = require("fs");
+                const name = Buffer.from("watermark");
+                const data = Buffer.from(process.env.WASM_JS_OUT_NAME);
+                const body = Buffer.concat([Buffer.from([name.length]), name, data]);
+                const wasm = fs.readFileSync(process.argv[2]);
+                const section = Buffer.concat([Buffer.from([0, body.length]), body]);
+                fs.writeFileSync(process.env.WASM_JS_WASM_PATH, Buffer.concat([wasm, section]));
+            "#,
+        )
+        .hello_world_src_lib()
+        .install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "Post-processing wasm with `node scripts/watermark.js {wasm}`...",
+        ));
+
+    let js = fs::read_to_string(fixture.path.join("dist/foo.js")).unwrap();
+    let sections = utils::wasm::custom_section_names(&utils::wasm::embedded_wasm(&js));
+    assert!(
+        sections.iter().any(|name| name == "watermark"),
+        "{:?}",
+        sections
+    );
+}
+
+#[test]
+fn build_with_post_process_that_breaks_the_module_fails() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "=0.2.95"
+
+                [package.metadata.wasm-js.profile.release]
+                post-process = [
+                    ["node", "-e", "require('fs').writeFileSync(process.argv[1], 'nope')", "{wasm}"],
+                ]
+            "#,
+        )
+        .hello_world_src_lib()
+        .install_local_wasm_bindgen();
+    fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("left an invalid wasm module"));
+}
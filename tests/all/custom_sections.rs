@@ -0,0 +1,86 @@
+use wasm_js::custom_sections;
+
+/// Encode a section with `id` and `contents`.
+fn section(id: u8, contents: &[u8]) -> Vec<u8> {
+    assert!(contents.len() < 0x80);
+    let mut bytes = vec![id, contents.len() as u8];
+    bytes.extend_from_slice(contents);
+    bytes
+}
+
+/// Encode a custom section called `name`.
+fn custom(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut contents = vec![name.len() as u8];
+    contents.extend_from_slice(name.as_bytes());
+    contents.extend_from_slice(data);
+    section(0, &contents)
+}
+
+/// A module with a type section among custom sections.
+fn module() -> Vec<u8> {
+    let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+    wasm.extend(custom("producers", b"\x01\x08language\x01\x04Rust\x00"));
+    // One type: `[] -> []`
+    wasm.extend(section(1, &[0x01, 0x60, 0x00, 0x00]));
+    wasm.extend(custom("name", b"\x00\x04\x03foo"));
+    wasm.extend(custom("target_features", b"\x00"));
+    wasm.extend(custom(".debug_info", &[0; 20]));
+    wasm.extend(custom(".debug_line", &[0; 10]));
+    wasm.extend(custom("sourceMappingURL", b"\x07foo.map"));
+    wasm
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+fn stripped(keep: &[&str], strip: &[&str]) -> (Vec<String>, Vec<String>) {
+    let wasm = module();
+    let (out, removed) = custom_sections::strip(&wasm, &strings(keep), &strings(strip)).unwrap();
+    wasmparser::Validator::new().validate_all(&out).unwrap();
+    assert_eq!(
+        wasm.len() - out.len(),
+        removed.iter().map(|section| section.size).sum::<usize>()
+    );
+    let removed = removed.into_iter().map(|section| section.name).collect();
+    (crate::utils::wasm::custom_section_names(&out), removed)
+}
+
+#[test]
+fn nothing_is_removed_by_default() {
+    let (sections, removed) = stripped(&[], &[]);
+    assert_eq!(sections.len(), 6);
+    assert!(removed.is_empty());
+}
+
+#[test]
+fn strip_sections_removes_matching_sections() {
+    let (sections, removed) = stripped(&[], &["producers", ".debug_*"]);
+    assert_eq!(sections, ["name", "target_features", "sourceMappingURL"]);
+    assert_eq!(removed, ["producers", ".debug_info", ".debug_line"]);
+}
+
+#[test]
+fn keep_sections_removes_everything_else() {
+    let (sections, removed) = stripped(&["name", ".debug_*"], &[".debug_line"]);
+    assert_eq!(sections, ["name", ".debug_info"]);
+    assert_eq!(
+        removed,
+        [
+            "producers",
+            "target_features",
+            ".debug_line",
+            "sourceMappingURL"
+        ]
+    );
+}
+
+#[test]
+fn invalid_patterns_are_reported() {
+    let err = custom_sections::strip(&module(), &[], &strings(&["[name"]))
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains("`strip-sections` has an invalid pattern `[name`"));
+}
@@ -0,0 +1,388 @@
+use oxc_allocator::Allocator;
+use oxc_ast::ast::{
+    ArrowFunctionExpression, AssignmentExpression, AwaitExpression, BigIntLiteral,
+    BinaryExpression, CatchClause, ChainExpression, ForOfStatement, Function, ImportExpression,
+    LogicalExpression, ObjectExpression, ObjectPropertyKind, PropertyDefinition,
+};
+use oxc_ast_visit::{walk, Visit};
+use oxc_parser::Parser;
+use oxc_span::SourceType;
+use oxc_syntax::operator::{AssignmentOperator, BinaryOperator, LogicalOperator};
+use oxc_syntax::scope::ScopeFlags;
+use std::io::Write;
+use wasm_js::js_bin::{self, LoaderOptions, LoaderTarget, RuntimeOptions, WasmJsWriter};
+
+/// Records the newest ECMAScript syntax used by a program, and where.
+#[derive(Default)]
+struct EsVersion {
+    year: u32,
+    feature: &'static str,
+}
+
+impl EsVersion {
+    fn require(&mut self, year: u32, feature: &'static str) {
+        if year > self.year {
+            self.year = year;
+            self.feature = feature;
+        }
+    }
+}
+
+impl<'a> Visit<'a> for EsVersion {
+    fn visit_function(&mut self, it: &Function<'a>, flags: ScopeFlags) {
+        if it.r#async && it.generator {
+            self.require(2018, "async generator");
+        } else if it.r#async {
+            self.require(2017, "async function");
+        }
+        walk::walk_function(self, it, flags);
+    }
+
+    fn visit_arrow_function_expression(&mut self, it: &ArrowFunctionExpression<'a>) {
+        if it.r#async {
+            self.require(2017, "async arrow function");
+        }
+        walk::walk_arrow_function_expression(self, it);
+    }
+
+    fn visit_await_expression(&mut self, it: &AwaitExpression<'a>) {
+        self.require(2017, "await");
+        walk::walk_await_expression(self, it);
+    }
+
+    fn visit_binary_expression(&mut self, it: &BinaryExpression<'a>) {
+        if it.operator == BinaryOperator::Exponential {
+            self.require(2016, "exponentiation operator");
+        }
+        walk::walk_binary_expression(self, it);
+    }
+
+    fn visit_object_expression(&mut self, it: &ObjectExpression<'a>) {
+        if it
+            .properties
+            .iter()
+            .any(|p| matches!(p, ObjectPropertyKind::SpreadProperty(_)))
+        {
+            self.require(2018, "object spread");
+        }
+        walk::walk_object_expression(self, it);
+    }
+
+    fn visit_for_of_statement(&mut self, it: &ForOfStatement<'a>) {
+        if it.r#await {
+            self.require(2018, "for await");
+        }
+        walk::walk_for_of_statement(self, it);
+    }
+
+    fn visit_catch_clause(&mut self, it: &CatchClause<'a>) {
+        if it.param.is_none() {
+            self.require(2019, "optional catch binding");
+        }
+        walk::walk_catch_clause(self, it);
+    }
+
+    fn visit_import_expression(&mut self, it: &ImportExpression<'a>) {
+        self.require(2020, "dynamic import");
+        walk::walk_import_expression(self, it);
+    }
+
+    fn visit_chain_expression(&mut self, it: &ChainExpression<'a>) {
+        self.require(2020, "optional chaining");
+        walk::walk_chain_expression(self, it);
+    }
+
+    fn visit_logical_expression(&mut self, it: &LogicalExpression<'a>) {
+        if it.operator == LogicalOperator::Coalesce {
+            self.require(2020, "nullish coalescing");
+        }
+        walk::walk_logical_expression(self, it);
+    }
+
+    fn visit_big_int_literal(&mut self, it: &BigIntLiteral<'a>) {
+        self.require(2020, "BigInt literal");
+        walk::walk_big_int_literal(self, it);
+    }
+
+    fn visit_assignment_expression(&mut self, it: &AssignmentExpression<'a>) {
+        match it.operator {
+            AssignmentOperator::Exponential => self.require(2016, "exponentiation assignment"),
+            AssignmentOperator::LogicalOr
+            | AssignmentOperator::LogicalAnd
+            | AssignmentOperator::LogicalNullish => self.require(2021, "logical assignment"),
+            _ => {}
+        }
+        walk::walk_assignment_expression(self, it);
+    }
+
+    fn visit_property_definition(&mut self, it: &PropertyDefinition<'a>) {
+        self.require(2022, "class field");
+        walk::walk_property_definition(self, it);
+    }
+}
+
+fn generate_loader(options: LoaderOptions) -> String {
+    let mut out = Vec::new();
+    {
+        let mut writer = WasmJsWriter::new(&mut out, "./foo_bg.js", options);
+        // The writer doesn't look at the bytes, so they don't have to be wasm.
+        writer.write_all(&[0x42; 100_000]).unwrap();
+        writer.flush().unwrap();
+    }
+    String::from_utf8(out).unwrap()
+}
+
+fn generate_runtime(target: LoaderTarget) -> String {
+    generate_runtime_with(RuntimeOptions {
+        target,
+        snapshot: true,
+    })
+}
+
+fn generate_runtime_with(options: RuntimeOptions) -> String {
+    let mut out = Vec::new();
+    js_bin::write_runtime(&mut out, options).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn required_es_version(source: &str) -> EsVersion {
+    let allocator = Allocator::default();
+    let ret = Parser::new(&allocator, source, SourceType::mjs()).parse();
+    assert!(ret.errors.is_empty(), "{:?}\n{}", ret.errors, source);
+    let mut version = EsVersion {
+        year: 2015,
+        feature: "",
+    };
+    version.visit_program(&ret.program);
+    version
+}
+
+fn target_year(target: LoaderTarget) -> u32 {
+    match target {
+        LoaderTarget::Es2015 => 2015,
+        LoaderTarget::Es2017 => 2017,
+        LoaderTarget::Es2020 => 2020,
+    }
+}
+
+#[test]
+fn runtime_only_uses_syntax_allowed_by_its_target() {
+    for target in [
+        LoaderTarget::Es2015,
+        LoaderTarget::Es2017,
+        LoaderTarget::Es2020,
+    ] {
+        let version = required_es_version(&generate_runtime(target));
+        assert!(
+            version.year <= target_year(target),
+            "the {} runtime uses {} from ES{}",
+            target,
+            version.feature,
+            version.year,
+        );
+    }
+}
+
+#[test]
+fn runtime_records_its_options() {
+    for target in [
+        LoaderTarget::Es2015,
+        LoaderTarget::Es2017,
+        LoaderTarget::Es2020,
+    ] {
+        for snapshot in [false, true] {
+            let options = RuntimeOptions { target, snapshot };
+            assert_eq!(
+                js_bin::runtime_options(&generate_runtime_with(options)),
+                Some(options)
+            );
+        }
+    }
+    assert_eq!(
+        js_bin::runtime_options("export function loadWasm() {}"),
+        None
+    );
+}
+
+#[test]
+fn runtime_only_has_snapshots_when_a_module_uses_them() {
+    let source = generate_runtime_with(RuntimeOptions {
+        target: LoaderTarget::Es2020,
+        snapshot: false,
+    });
+    assert!(source.contains("export function loadWasm("));
+    assert!(!source.contains("snapshotWasm"));
+    assert!(!source.contains("restoreWasm"));
+    assert!(generate_runtime(LoaderTarget::Es2020).contains("export function snapshotWasm("));
+}
+
+#[test]
+fn loader_only_uses_es2015_syntax() {
+    for snapshot in [false, true] {
+        let source = generate_loader(LoaderOptions {
+            snapshot,
+            build_id: "0123456789abcdef".into(),
+            ..Default::default()
+        });
+        let version = required_es_version(&source);
+        assert_eq!(version.year, 2015, "the loader uses {}", version.feature);
+    }
+}
+
+#[test]
+fn loader_leaves_instantiation_to_the_runtime() {
+    let source = generate_loader(LoaderOptions {
+        snapshot: true,
+        ..Default::default()
+    });
+    assert!(source.contains(&format!("from './{}'", js_bin::RUNTIME_FILENAME)));
+    assert!(source.contains("runtime.loadWasm(CHUNKS, './foo_bg.js', importObject)"));
+    assert!(source.contains("runtime.snapshotWasm(WASM, BUILD_ID)"));
+    assert!(!source.contains("DecompressionStream"));
+    assert!(!source.contains("WebAssembly"));
+}
+
+#[test]
+fn legacy_runtimes_avoid_newer_apis() {
+    for target in [LoaderTarget::Es2015, LoaderTarget::Es2017] {
+        let source = generate_runtime(target);
+        assert!(!source.contains(".bytes()"));
+        assert!(source.contains(".arrayBuffer()"));
+    }
+    let source = generate_runtime(LoaderTarget::Es2015);
+    assert!(!source.contains("type: 'bytes'"));
+}
+
+#[test]
+fn wasm2js_fallback_needs_es2020() {
+    let source = generate_loader(LoaderOptions {
+        wasm2js_module: Some("./foo_wasm2js.js".into()),
+        ..Default::default()
+    });
+    assert!(source.contains("import('./foo_wasm2js.js')"));
+    let version = required_es_version(&source);
+    assert_eq!(version.year, 2020);
+    assert_eq!(version.feature, "dynamic import");
+}
+
+#[test]
+fn all_runtimes_fall_back_to_wasm2js() {
+    // A directory shared with an older loader gets the older runtime, which
+    // must still honor the fallback of a newer loader
+    for target in [
+        LoaderTarget::Es2015,
+        LoaderTarget::Es2017,
+        LoaderTarget::Es2020,
+    ] {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("wasm-js-runtime.js"),
+            generate_runtime(target),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("run.mjs"),
+            r#"
+                delete globalThis.WebAssembly;
+                const runtime = await import('./wasm-js-runtime.js');
+                let exports = null;
+                let started = false;
+                const importObject = { __wbg_set_wasm: (e) => { exports = e; } };
+                const fallback = {
+                    answer: () => 42,
+                    __wbindgen_start: () => { started = true; }
+                };
+                const wasm = runtime.loadWasm([], './foo_bg.js', importObject, () => Promise.resolve(fallback));
+                await wasm.promise;
+                console.log(JSON.stringify([exports.answer(), started]));
+            "#,
+        )
+        .unwrap();
+        let output = std::process::Command::new("node")
+            .arg(dir.path().join("run.mjs"))
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{:?}: {}",
+            target,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "[42,true]");
+    }
+}
+
+#[test]
+fn node_uses_zlib_for_all_targets() {
+    for target in [
+        LoaderTarget::Es2015,
+        LoaderTarget::Es2017,
+        LoaderTarget::Es2020,
+    ] {
+        let source = generate_runtime(target);
+        assert!(source.contains("process.getBuiltinModule('zlib')"));
+        assert!(source.contains("zlib.inflateSync(compressed)"));
+        // Bundlers must not see a static dependency on Node modules
+        assert!(!source.contains("from 'zlib'"));
+        assert!(!source.contains("from 'node:zlib'"));
+    }
+}
+
+#[test]
+fn baseline_variant_needs_es2020() {
+    let source = generate_loader(LoaderOptions {
+        baseline_module: Some("./foo_baseline.js".into()),
+        feature_probes: vec![js_bin::feature_probe("simd128").unwrap()],
+        ..Default::default()
+    });
+    assert!(source.contains("import('./foo_baseline.js')"));
+    let version = required_es_version(&source);
+    assert_eq!(version.year, 2020);
+    assert_eq!(version.feature, "dynamic import");
+}
+
+#[test]
+fn baseline_module_only_exports_chunks() {
+    let mut out = Vec::new();
+    {
+        let mut writer = WasmJsWriter::chunks_only(&mut out);
+        writer.write_all(&[0x42; 100_000]).unwrap();
+        writer.flush().unwrap();
+    }
+    let source = String::from_utf8(out).unwrap();
+    assert!(source.contains("export const CHUNKS"));
+    assert!(!source.contains("import"));
+    assert!(!source.contains("loadWasm"));
+    let version = required_es_version(&source);
+    assert_eq!(version.year, 2015, "the module uses {}", version.feature);
+}
+
+#[test]
+fn feature_probes_only_validate_with_their_feature() {
+    use wasmparser::{Validator, WasmFeatures};
+
+    for (feature, flag) in [
+        ("simd128", WasmFeatures::SIMD),
+        ("relaxed-simd", WasmFeatures::RELAXED_SIMD),
+        ("bulk-memory", WasmFeatures::BULK_MEMORY),
+        ("sign-ext", WasmFeatures::SIGN_EXTENSION),
+        ("nontrapping-fptoint", WasmFeatures::SATURATING_FLOAT_TO_INT),
+        ("multivalue", WasmFeatures::MULTI_VALUE),
+        ("reference-types", WasmFeatures::REFERENCE_TYPES),
+        ("tail-call", WasmFeatures::TAIL_CALL),
+        ("extended-const", WasmFeatures::EXTENDED_CONST),
+    ] {
+        let probe = js_bin::feature_probe(feature).unwrap();
+        Validator::new_with_features(WasmFeatures::all())
+            .validate_all(probe)
+            .unwrap_or_else(|e| panic!("the {} probe is invalid: {}", feature, e));
+        assert!(
+            Validator::new_with_features(WasmFeatures::all() - flag)
+                .validate_all(probe)
+                .is_err(),
+            "the {} probe validates without it",
+            feature
+        );
+    }
+    assert!(js_bin::feature_probe("atomics").is_none());
+}
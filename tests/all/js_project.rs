@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::Path;
+use wasm_js::js_project::infer_out_dir;
+
+fn write(dir: &Path, path: &str, contents: &str) {
+    let path = dir.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+#[test]
+fn no_js_project() {
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(infer_out_dir(dir.path(), true).unwrap(), None);
+}
+
+#[test]
+fn tsconfig_out_dir_through_extends() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    write(
+        &root,
+        "tsconfig.json",
+        r#"{
+            // comments and trailing commas are allowed
+            "extends": ["@acme/tsconfig", "./config/base"],
+            "compilerOptions": { "strict": true, /* "outDir": "nope" */ },
+        }"#,
+    );
+    write(
+        &root,
+        "config/base.json",
+        r#"{ "compilerOptions": { "outDir": "../build/js" } }"#,
+    );
+    write(
+        &root,
+        "node_modules/@acme/tsconfig/tsconfig.json",
+        r#"{ "compilerOptions": { "outDir": "ignored" } }"#,
+    );
+
+    let out_dir = infer_out_dir(&root, true).unwrap().unwrap();
+    assert_eq!(out_dir.path, root.join("config/../build/js"));
+    assert_eq!(out_dir.key, "compilerOptions.outDir");
+    assert_eq!(out_dir.file, root.join("config/base.json"));
+}
+
+#[test]
+fn tsconfig_out_dir_from_a_package() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    write(
+        &root,
+        "app/tsconfig.json",
+        r#"{ "extends": "@acme/tsconfig" }"#,
+    );
+    write(
+        &root,
+        "node_modules/@acme/tsconfig/tsconfig.json",
+        r#"{ "compilerOptions": { "outDir": "out" } }"#,
+    );
+
+    let out_dir = infer_out_dir(&root.join("app"), true).unwrap().unwrap();
+    assert_eq!(out_dir.path, root.join("node_modules/@acme/tsconfig/out"));
+}
+
+#[test]
+fn tsconfig_out_dir_needs_opting_in() {
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        "tsconfig.json",
+        r#"{ "compilerOptions": { "outDir": "lib" } }"#,
+    );
+    assert_eq!(infer_out_dir(dir.path(), false).unwrap(), None);
+
+    write(
+        dir.path(),
+        "package.json",
+        r#"{ "name": "app", "wasm-js": { "outDir": "src/wasm" } }"#,
+    );
+    let out_dir = infer_out_dir(dir.path(), false).unwrap().unwrap();
+    assert_eq!(out_dir.path, dir.path().join("src/wasm"));
+}
+
+#[test]
+fn package_json_takes_precedence() {
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        "tsconfig.json",
+        r#"{ "compilerOptions": { "outDir": "lib" } }"#,
+    );
+    write(
+        dir.path(),
+        "package.json",
+        r#"{ "name": "app", "wasm-js": { "outDir": "src/wasm" } }"#,
+    );
+
+    let out_dir = infer_out_dir(dir.path(), true).unwrap().unwrap();
+    assert_eq!(out_dir.path, dir.path().join("src/wasm"));
+    assert_eq!(out_dir.key, "wasm-js.outDir");
+}
+
+#[test]
+fn tsconfig_extends_cycle_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "tsconfig.json", r#"{ "extends": "./a.json" }"#);
+    write(dir.path(), "a.json", r#"{ "extends": "./tsconfig.json" }"#);
+
+    let error = infer_out_dir(dir.path(), true).unwrap_err().to_string();
+    assert!(error.ends_with("tsconfig.json extends itself"), "{}", error);
+}
+
+#[test]
+fn tsconfig_missing_base_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "tsconfig.json", r#"{ "extends": "./nope" }"#);
+
+    let error = infer_out_dir(dir.path(), true).unwrap_err().to_string();
+    assert!(error.starts_with("can't find `./nope`"), "{}", error);
+}
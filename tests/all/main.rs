@@ -11,7 +11,12 @@ extern crate clap;
 extern crate tempfile;
 
 mod build;
+mod custom_sections;
+mod js_bin;
+mod js_project;
 mod log_level;
+mod pre_initialize;
 mod stamps;
 mod utils;
 mod wasm_opt;
+mod wasm_snip;
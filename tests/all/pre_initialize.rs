@@ -0,0 +1,196 @@
+use wasm_encoder::{
+    CodeSection, EntityType, ExportKind, ExportSection, Function, FunctionSection, ImportSection,
+    InstructionSink, MemoryType, TypeSection, ValType,
+};
+use wasm_js::pre_initialize;
+use wasmparser::{FuncType, Operator, Parser, Payload, Validator};
+
+/// A module that imports `imports`, and exports a `__wbindgen_start` that runs
+/// `body` when `start` is set.
+fn module(
+    imports: &[(&str, &str, EntityType)],
+    types: TypeSection,
+    start: bool,
+    body: impl FnOnce(&mut InstructionSink),
+) -> Vec<u8> {
+    let mut types = types;
+    let start_type = types.len();
+    types.ty().function([], []);
+    let mut import_section = ImportSection::new();
+    let mut imported_functions = 0;
+    for &(module, name, ty) in imports {
+        import_section.import(module, name, ty);
+        if let EntityType::Function(_) = ty {
+            imported_functions += 1;
+        }
+    }
+    let mut functions = FunctionSection::new();
+    functions.function(start_type);
+    let mut exports = ExportSection::new();
+    if start {
+        exports.export("__wbindgen_start", ExportKind::Func, imported_functions);
+    }
+    let mut function = Function::new([]);
+    body(&mut function.instructions());
+    function.instructions().end();
+    let mut code = CodeSection::new();
+    code.function(&function);
+
+    let mut module = wasm_encoder::Module::new();
+    module
+        .section(&types)
+        .section(&import_section)
+        .section(&functions)
+        .section(&exports)
+        .section(&code);
+    module.finish()
+}
+
+/// The exported functions of `stub` with their types and whether their body
+/// traps right away.
+fn stub_functions(stub: &[u8]) -> Vec<(String, FuncType, bool)> {
+    let mut types = Vec::new();
+    let mut exports = Vec::new();
+    let mut bodies = Vec::new();
+    for payload in Parser::new(0).parse_all(stub) {
+        match payload.unwrap() {
+            Payload::TypeSection(reader) => {
+                types.extend(reader.into_iter_err_on_gc_types().map(Result::unwrap))
+            }
+            Payload::ExportSection(reader) => {
+                exports.extend(reader.into_iter().map(|e| e.unwrap().name.to_string()))
+            }
+            Payload::CodeSectionEntry(body) => {
+                let first = body.get_operators_reader().unwrap().read().unwrap();
+                bodies.push(matches!(first, Operator::Unreachable));
+            }
+            _ => {}
+        }
+    }
+    exports
+        .into_iter()
+        .zip(types)
+        .zip(bodies)
+        .map(|((name, ty), traps)| (name, ty, traps))
+        .collect()
+}
+
+#[test]
+fn stubs_export_each_import_with_its_type() {
+    let mut types = TypeSection::new();
+    types.ty().function([], [ValType::F64]);
+    types.ty().function([], []);
+    types.ty().function([ValType::EXTERNREF, ValType::I32], []);
+    types.ty().function([ValType::I64], [ValType::I32]);
+    let wasm = module(
+        &[
+            ("./foo_bg.js", "__wbg_random_1", EntityType::Function(0)),
+            (
+                "./foo_bg.js",
+                "__wbindgen_init_externref_table",
+                EntityType::Function(1),
+            ),
+            ("wbg", "__wbg_len_2", EntityType::Function(3)),
+            ("./foo_bg.js", "__wbg_log_3", EntityType::Function(2)),
+        ],
+        types,
+        true,
+        |_| {},
+    );
+    Validator::new().validate_all(&wasm).unwrap();
+
+    let stubs = pre_initialize::import_stubs(&wasm).unwrap();
+    let names: Vec<&str> = stubs.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["./foo_bg.js", "wbg"]);
+    for (_, stub) in &stubs {
+        Validator::new().validate_all(stub).unwrap();
+    }
+
+    let foo = stub_functions(&stubs[0].1);
+    let summary: Vec<(&str, &[wasmparser::ValType], &[wasmparser::ValType], bool)> = foo
+        .iter()
+        .map(|(name, ty, traps)| (name.as_str(), ty.params(), ty.results(), *traps))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (
+                "__wbg_random_1",
+                &[][..],
+                &[wasmparser::ValType::F64][..],
+                true
+            ),
+            ("__wbindgen_init_externref_table", &[][..], &[][..], false),
+            (
+                "__wbg_log_3",
+                &[wasmparser::ValType::EXTERNREF, wasmparser::ValType::I32][..],
+                &[][..],
+                true
+            ),
+        ]
+    );
+    let wbg = stub_functions(&stubs[1].1);
+    assert_eq!(wbg.len(), 1);
+    assert_eq!(wbg[0].0, "__wbg_len_2");
+    assert_eq!(wbg[0].1.params(), [wasmparser::ValType::I64]);
+    assert!(wbg[0].2);
+    assert_eq!(
+        crate::utils::wasm::function_names(&stubs[1].1),
+        ["__wbg_len_2"]
+    );
+}
+
+#[test]
+fn module_that_imports_its_memory_fails() {
+    let memory = MemoryType {
+        minimum: 1,
+        maximum: None,
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    };
+    let wasm = module(
+        &[("./foo_bg.js", "memory", EntityType::Memory(memory))],
+        TypeSection::new(),
+        true,
+        |_| {},
+    );
+    let err = pre_initialize::import_stubs(&wasm).unwrap_err();
+    assert!(err.to_string().contains(
+        "the module imports `memory` from `./foo_bg.js`, which isn't available at build time"
+    ));
+}
+
+#[test]
+fn module_without_start_function_fails() {
+    let wasm = module(&[], TypeSection::new(), false, |_| {});
+    let err = pre_initialize::import_stubs(&wasm).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("the module doesn't export `__wbindgen_start`"));
+}
+
+#[test]
+fn module_with_simd_passes_the_feature_check() {
+    let wasm = module(&[], TypeSection::new(), true, |start| {
+        start.v128_const(1).v128_const(2).f32x4_add().drop();
+    });
+    pre_initialize::check_features(&wasm).unwrap();
+}
+
+#[test]
+fn module_with_relaxed_simd_fails_the_feature_check() {
+    let wasm = module(&[], TypeSection::new(), true, |start| {
+        start
+            .v128_const(1)
+            .v128_const(2)
+            .v128_const(3)
+            .f32x4_relaxed_madd()
+            .drop();
+    });
+    let err = pre_initialize::check_features(&wasm).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("wizer can only pre-initialize wasm that uses the WebAssembly 2.0 features"));
+    assert!(err.to_string().contains("relaxed SIMD"));
+}
@@ -419,3 +419,61 @@ pub fn transitive_dependencies() -> Fixture {
     project_main_fixture(&mut fixture);
     fixture
 }
+
+/// A workspace with two wasm members, `codec` and `parser`, and a plain
+/// library member, `tool`, that isn't meant to be built for wasm.
+pub fn wasm_workspace() -> Fixture {
+    fn wasm_member(fixture: &Fixture, name: &str) {
+        fixture.file(
+            PathBuf::from(name).join("Cargo.toml"),
+            format!(
+                r#"
+                    [package]
+                    name = "{}"
+                    version = "0.1.0"
+                    edition = "2021"
+
+                    [lib]
+                    crate-type = ["cdylib"]
+
+                    [dependencies]
+                    wasm-bindgen = "=0.2.95"
+                "#,
+                name
+            ),
+        );
+        fixture.file(
+            PathBuf::from(name).join("src/lib.rs"),
+            r#"
+                use wasm_bindgen::prelude::*;
+
+                #[wasm_bindgen]
+                pub fn double(x: u32) -> u32 {
+                    x * 2
+                }
+            "#,
+        );
+    }
+
+    let fixture = Fixture::new();
+    fixture.file(
+        "Cargo.toml",
+        r#"
+            [workspace]
+            members = ["codec", "parser", "tool"]
+        "#,
+    );
+    wasm_member(&fixture, "codec");
+    wasm_member(&fixture, "parser");
+    fixture
+        .file(
+            "tool/Cargo.toml",
+            r#"
+                [package]
+                name = "tool"
+                version = "0.1.0"
+            "#,
+        )
+        .file("tool/src/lib.rs", "");
+    fixture
+}
@@ -0,0 +1,79 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::read::ZlibDecoder;
+use std::io::Read;
+use wasmparser::{KnownCustom, Name, Parser, Payload};
+
+/// Decode the wasm module embedded in a Javascript module generated by
+/// `wasm-js build`.
+pub fn embedded_wasm(js: &str) -> Vec<u8> {
+    let start = js.find("const CHUNKS = [").expect("no chunk table");
+    let end = start + js[start..].find("];").unwrap();
+    let base64: String = js[start..end]
+        .lines()
+        .skip(1)
+        .map(|line| line.trim().trim_end_matches(',').trim_matches('"'))
+        .collect();
+    let compressed = STANDARD.decode(base64).unwrap();
+    let mut wasm = Vec::new();
+    ZlibDecoder::new(&compressed[..])
+        .read_to_end(&mut wasm)
+        .unwrap();
+    wasm
+}
+
+/// The function names from the name section of `wasm`.
+pub fn function_names(wasm: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::CustomSection(section) = payload.unwrap() {
+            if let KnownCustom::Name(reader) = section.as_known() {
+                for name in reader {
+                    if let Name::Function(map) = name.unwrap() {
+                        for naming in map {
+                            names.push(naming.unwrap().name.to_string());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    names
+}
+
+/// The names of the custom sections of `wasm`, in order.
+pub fn custom_section_names(wasm: &[u8]) -> Vec<String> {
+    Parser::new(0)
+        .parse_all(wasm)
+        .filter_map(|payload| match payload.unwrap() {
+            Payload::CustomSection(section) => Some(section.name().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The names of the exports of `wasm`.
+pub fn export_names(wasm: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::ExportSection(reader) = payload.unwrap() {
+            for export in reader {
+                names.push(export.unwrap().name.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// The contents of the data segments of `wasm`, in order.
+pub fn data_segments(wasm: &[u8]) -> Vec<Vec<u8>> {
+    let mut segments = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::DataSection(reader) = payload.unwrap() {
+            for data in reader {
+                segments.push(data.unwrap().data.to_vec());
+            }
+        }
+    }
+    segments
+}
@@ -187,3 +187,73 @@ fn misconfigured() {
         .stderr(predicates::str::contains("failed to parse manifest"))
         .failure();
 }
+
+#[test]
+fn auto_keeps_the_smallest_compressed_result() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                authors = []
+                description = ""
+                license = "MIT"
+                name = "foo"
+                repository = ""
+                version = "0.1.0"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "0.2"
+
+                [package.metadata.wasm-js.profile.release]
+                wasm-opt = "auto"
+            "#,
+        )
+        .hello_world_src_lib();
+    fixture.install_local_wasm_bindgen();
+    fixture.install_wasm_opt();
+
+    let output = fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Optimizing wasm binaries with 8 sets of `wasm-opt` flags"));
+    assert!(stderr.contains("Compressed sizes of foo with each set of `wasm-opt` flags:"));
+    assert!(stderr.contains("-Oz --converge"));
+    assert_eq!(stderr.matches("(kept)").count(), 1);
+    assert!(fixture.path.join("dist").join("foo.js").exists());
+    // The losing candidates are cleaned up
+    let temp_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("wasm-bindgen");
+    for i in 0..8 {
+        assert!(!temp_dir
+            .join(format!("foo_bg.wasm-opt-{}.wasm", i))
+            .exists());
+    }
+}
+
+#[test]
+fn target_features_map_to_wasm_opt_flags() {
+    let features: Vec<String> = ["simd128", "+nontrapping-fptoint", "-sign-ext", "crt-static"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(
+        wasm_js::wasm_opt::feature_args(&features),
+        [
+            "--enable-simd",
+            "--enable-nontrapping-float-to-int",
+            "--disable-sign-ext",
+        ]
+    );
+}
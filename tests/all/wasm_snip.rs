@@ -0,0 +1,100 @@
+use crate::utils;
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+#[test]
+fn off_by_default() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture.readme().cargo_toml("foo").file("src/lib.rs", "");
+    fixture.install_local_wasm_bindgen();
+
+    fixture
+        .wasm_js()
+        .arg("build")
+        .arg("--dev")
+        .assert()
+        .stderr(predicates::str::contains("wasm-snip").not())
+        .success();
+}
+
+#[test]
+fn runs_before_wasm_opt() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                authors = []
+                description = ""
+                license = "MIT"
+                name = "foo"
+                repository = ""
+                version = "0.1.0"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                wasm-bindgen = "0.2"
+
+                [package.metadata.wasm-js.profile.release.wasm-snip]
+                patterns = ["core::fmt::.*"]
+                snip-rust-panicking-code = true
+            "#,
+        )
+        .hello_world_src_lib();
+    fixture.install_local_wasm_bindgen();
+    fixture.install_wasm_opt();
+
+    let output = fixture
+        .wasm_js()
+        .arg("--verbose")
+        .arg("build")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("wasm-snip.patterns = [\"core::fmt::.*\"]"));
+    assert!(stderr.contains("wasm-snip.snip-rust-panicking-code = true"));
+    let snip = stderr.find("Snipping functions with `wasm-snip`").unwrap();
+    let opt = stderr
+        .find("Optimizing wasm binaries with `wasm-opt`")
+        .unwrap();
+    assert!(snip < opt);
+}
+
+#[test]
+fn misconfigured() {
+    let fixture = utils::fixture::Fixture::new();
+    fixture
+        .readme()
+        .file(
+            "Cargo.toml",
+            r#"
+                [package]
+                authors = []
+                description = ""
+                license = "MIT"
+                name = "foo"
+                repository = ""
+                version = "0.1.0"
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [package.metadata.wasm-js.profile.release.wasm-snip]
+                patterns = "core::fmt::.*"
+            "#,
+        )
+        .file("src/lib.rs", "");
+
+    fixture
+        .wasm_js()
+        .arg("build")
+        .assert()
+        .stderr(predicates::str::contains("failed to parse manifest"))
+        .failure();
+}